@@ -1,16 +1,25 @@
 use std::path::PathBuf;
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use petgraph::dot::{Config, Dot};
+use petgraph::graph::EdgeReference;
 
 use anvil_context::{intern, resolve, syms};
-use anvil_parse::ASTBuilder;
-use anvil_runtime::{run_repl, Executor, ExecNode, ExecEdge, Planner};
+use anvil_parse::{bind_params, format_script, load_imports, render, ASTBuilder, Sources};
+use anvil_runtime::{run_repl, tool_help, tools_help, EdgeKind, Executor, ExecNode, ExecEdge, ExecutionPlan, Inferred, Planner};
 
 
 #[derive(Parser)]
+#[command(
+    args_conflicts_with_subcommands = true,
+    disable_help_subcommand = true,
+    after_help = "Run 'anvil help [TOOL]' to list the tools or describe one",
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to the Anvil script to run, not provided run repl
     script: Option<PathBuf>,
 
@@ -22,37 +31,114 @@ struct Cli {
         num_args = 0..=1,
     )]
     dot: Option<Option<PathBuf>>,
+
+    /// Maximum number of tools to run concurrently (defaults to the number of CPUs)
+    #[arg(short = 'j', long = "jobs", value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Cache results read by more than one downstream tool
+    #[arg(short = 'c', long = "cache")]
+    cache: bool,
+
+    /// Value of a parameter the script declares, may be repeated
+    #[arg(
+        short = 'p',
+        long = "param",
+        value_name = "NAME=VALUE",
+        value_parser = parse_param,
+        requires = "script",
+    )]
+    params: Vec<(String, String)>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Describe a tool's arguments and ports, or list the tools
+    Help {
+        tool: Option<String>,
+    },
+
+    /// Print a script formatted in canonical style
+    Fmt {
+        /// Path to the Anvil script to format
+        script: PathBuf,
+
+        /// Rewrite the script in place rather than printing it
+        #[arg(short = 'w', long = "write")]
+        write: bool,
+
+        /// Exit with an error if the script isn't formatted
+        #[arg(long = "check", conflicts_with = "write")]
+        check: bool,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()>
 {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Some(Command::Help { tool }) => {
+            match tool {
+                Some(tool) => println!("{}", tool_help(&tool)?),
+                None => println!("{}", tools_help()),
+            }
+            return Ok(())
+        }
+        Some(Command::Fmt { script, write, check }) => {
+            return format(script, write, check)
+        }
+        None => {}
+    }
+
     let mut builder  = ASTBuilder::new();
     let mut planner  = Planner::default();
-    let mut executor = Executor::default();
+    let mut executor = cli.jobs.map(Executor::new).unwrap_or_default();
+    executor.set_auto_cache(cli.cache);
 
-    let cli = Cli::parse();
     if let Some(script) = cli.script {
         let source = std::fs::read_to_string(&script)?;
-        if let Some(cmd) = cli.dot {
-            let program = anvil_parse::build_program(&mut builder, &source)?;
-            let plan = planner.build(program)?;
-            let dot  = Dot::with_attr_getters(
-                &plan,
-                &[Config::NodeIndexLabel], // optional
-                &|_, edge| edge_attrs(edge.weight()),
-                &|_, (_, node)| node_attrs(node),
-            );
-
-            if let Some(path) = cmd {
-                std::fs::write(path, dot.to_string())?;
+        let name = script.display().to_string();
+        let mut sources = Sources::default();
+        let result = async {
+            let mut program = anvil_parse::build_program(&mut builder, &source)?;
+            load_imports(&mut builder, &mut program, &script, &mut sources)?;
+            bind_params(&mut program, &cli.params)?;
+
+            if let Some(cmd) = cli.dot {
+                let plan = planner.build(program, &mut builder)?;
+                let inferred = executor.infer(plan).await?;
+                for problem in &inferred.problems {
+                    eprintln!("{}", problem.render(&name, &source, &sources));
+                }
+
+                let edge_label = |_, edge: EdgeReference<ExecEdge>| {
+                    edge_attrs(edge.weight(), columns(plan, edge, &inferred))
+                };
+                let dot  = Dot::with_attr_getters(
+                    &plan,
+                    &[Config::NodeIndexLabel, Config::EdgeNoLabel],
+                    &edge_label,
+                    &|_, (_, node)| node_attrs(node),
+                );
+
+                if let Some(path) = cmd {
+                    std::fs::write(path, dot.to_string())?;
+                } else {
+                    println!("{dot}")
+                }
             } else {
-                println!("{dot}")
+                let plan = planner.build(program, &mut builder)?;
+                executor.run(plan).await?
             }
-        } else {
-            let program = anvil_parse::build_program(&mut builder, &source)?;
-            let plan = planner.build(program)?;
-            executor.run(plan).await?
+            Ok(())
+        }.await;
+
+        // Errors are rendered with their location in the script
+        if let Err(e) = result {
+            eprintln!("{}", render(&e, &name, &source, &sources));
+            std::process::exit(1);
         }
     } else {
         run_repl(&mut builder, &mut planner, &mut executor).await?;
@@ -61,16 +147,53 @@ async fn main() -> Result<()>
     Ok(())
 }
 
+/// Print, rewrite or check the formatting of a script.
+fn format(script: PathBuf, write: bool, check: bool) -> Result<()>
+{
+    let source = std::fs::read_to_string(&script)?;
+    let name = script.display().to_string();
+    let formatted = match format_script(&source) {
+        Ok(formatted) => formatted,
+        Err(e) => {
+            eprintln!("{}", render(&e, &name, &source, &Sources::default()));
+            std::process::exit(1);
+        }
+    };
+
+    if check {
+        if formatted != source {
+            eprintln!("{name} is not formatted");
+            std::process::exit(1);
+        }
+    } else if write {
+        if formatted != source {
+            std::fs::write(&script, formatted)?;
+        }
+    } else {
+        print!("{formatted}");
+    }
+
+    Ok(())
+}
+
+/// A parameter given as NAME=VALUE.
+fn parse_param(param: &str) -> Result<(String, String), String>
+{
+    param.split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected NAME=VALUE, found '{param}'"))
+}
+
 fn node_attrs(node: &ExecNode) -> String
 {
     match node {
-        ExecNode::Tool(tool) => {
+        ExecNode::Tool(tool, ..) => {
             format!(
                 r#"label="{} ({})", shape=box, style=filled, fillcolor=lightblue"#,
                 tool.name(), tool.id()
             )
         }
-        ExecNode::Variable(sym) => {
+        ExecNode::Variable(sym, _) => {
             format!(
                 r#"label="{}", shape=ellipse, style=filled, fillcolor=lightgray"#,
                 resolve(*sym)
@@ -79,15 +202,38 @@ fn node_attrs(node: &ExecNode) -> String
     }
 }
 
-fn edge_attrs(edge: &ExecEdge) -> String
+/// Columns carried by an edge as 'name: type', empty if unknown.
+fn columns(plan: &ExecutionPlan, edge: EdgeReference<ExecEdge>, inferred: &Inferred) -> Vec<String>
+{
+    inferred.schema(plan, edge)
+        .map(|schema| {
+            schema.fields().iter()
+                .map(|f| format!("{}: {}", f.name(), f.data_type()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn edge_attrs(edge: &ExecEdge, columns: Vec<String>) -> String
 {
-    if edge.port == syms().default {
-        r#"label="""#.to_string()
+    // Port followed by the columns, one per line, left justified
+    let mut lines = vec![];
+    if edge.port != syms().default {
+        lines.push(resolve(edge.port).to_string());
+    }
+    lines.extend(columns);
+    let mut label = lines.join("\\l").replace('"', "\\\"");
+    if lines.len() > 1 || (edge.port == syms().default && !lines.is_empty()) {
+        label.push_str("\\l");
+    }
+
+    if edge.kind == EdgeKind::Order {
+        r#"label="", style=dashed, color=gray"#.to_string()
     } else if edge.port == intern("true") {
-        r#"label="true", color=green"#.to_string()
+        format!(r#"label="{label}", color=green"#)
     } else if edge.port == intern("false") {
-        r#"label="false", color=red"#.to_string()
+        format!(r#"label="{label}", color=red"#)
     } else {
-        format!(r#"label="{}""#, resolve(edge.port))
+        format!(r#"label="{label}""#)
     }
 }
@@ -35,6 +35,7 @@ pub fn syms() -> &'static Syms
             right: intern("right"),
             port_true: intern("true"),
             port_false: intern("false"),
+            port_else: intern("else"),
             join_type: intern("type"),
             cols_lt: intern("cols_lt"),
             cols_rt: intern("cols_rt")
@@ -48,6 +49,7 @@ pub struct Syms {
     pub right: Symbol,
     pub port_true: Symbol,
     pub port_false: Symbol,
+    pub port_else: Symbol,
     pub join_type: Symbol,
     pub cols_lt: Symbol,
     pub cols_rt: Symbol,
@@ -8,11 +8,13 @@ static TOOL_TYPES: OnceLock<HashMap<Symbol, ToolType>> = OnceLock::new();
 pub fn tool_types() -> &'static HashMap<Symbol, ToolType> {
     TOOL_TYPES.get_or_init(|| {
         HashMap::from([
+            (intern("aggregate"), ToolType::Aggregate),
+            (intern("cache"),     ToolType::Cache),
             (intern("count"),     ToolType::Count),
             (intern("describe"),  ToolType::Describe),
             (intern("distinct"),  ToolType::Distinct),
             (intern("drop"),      ToolType::Drop),
-            (intern("file"),      ToolType::Fill),
+            (intern("fill"),      ToolType::Fill),
             (intern("filter"),    ToolType::Filter),
             (intern("input"),     ToolType::Input),
             (intern("intersect"), ToolType::Intersect),
@@ -22,17 +24,21 @@ pub fn tool_types() -> &'static HashMap<Symbol, ToolType> {
             (intern("print"),     ToolType::Print),
             (intern("project"),   ToolType::Project),
             (intern("register"),  ToolType::Register),
+            (intern("route"),     ToolType::Route),
             (intern("schema"),    ToolType::Schema),
             (intern("select"),    ToolType::Select),
             (intern("sort"),      ToolType::Sort),
             (intern("sql"),       ToolType::Sql),
             (intern("union"),     ToolType::Union),
+            (intern("window"),    ToolType::Window),
         ])
     })
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum ToolType {
+    Aggregate,
+    Cache,
     Count,
     Describe,
     Distinct,
@@ -47,9 +53,11 @@ pub enum ToolType {
     Print,
     Project,
     Register,
+    Route,
     Schema,
     Select,
     Sort,
     Sql,
     Union,
+    Window,
 }
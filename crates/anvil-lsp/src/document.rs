@@ -0,0 +1,188 @@
+use tower_lsp::lsp_types::{Position, Range};
+
+use anvil_context::{intern, Symbol};
+use anvil_parse::anvil::ast::*;
+use anvil_parse::{recover_program, ASTBuilder, Diagnostic};
+
+/// An open script along with what parsed of it.
+pub struct Document {
+    pub text: String,
+    pub program: Program,
+    pub errors: Vec<Diagnostic>,
+}
+
+/// Where a variable appears in a script.
+#[derive(Clone, Copy)]
+pub struct Occurrence {
+    pub name: Symbol,
+    pub span: Span,
+    /// Bound with '>' or by a branch, rather than read.
+    pub binding: bool,
+}
+
+impl Document {
+    pub fn parse(text: String) -> Document
+    {
+        let (program, errors) = recover_program(&mut ASTBuilder::new(), &text);
+        Document { text, program, errors }
+    }
+
+    /// Every variable binding and use in source order. The parameters
+    /// and outputs of definitions are their own, not variables.
+    pub fn occurrences(&self) -> Vec<Occurrence>
+    {
+        let mut found = vec![];
+        for stmt in &self.program.statements {
+            body_occurrences(&stmt.flow, stmt.branches.as_deref(), &mut found);
+            if let Some((name, span)) = &stmt.variable {
+                found.push(Occurrence { name: *name, span: *span, binding: true });
+            }
+        }
+        for def in &self.program.defs {
+            let mut body = vec![];
+            body_occurrences(&def.flow, def.branches.as_deref(), &mut body);
+            found.extend(body.into_iter().filter(|o| {
+                !def.params.iter().any(|(param, _)| *param == o.name) && !def.outputs.contains(&o.name)
+            }));
+        }
+
+        found.sort_by_key(|o| o.span.start);
+        found
+    }
+
+    /// The variable binding or use at an offset.
+    pub fn occurrence_at(&self, offset: usize) -> Option<Occurrence>
+    {
+        self.occurrences().into_iter()
+            .find(|o| o.span.start <= offset && offset <= o.span.end)
+    }
+
+    /// Innermost tool whose brackets enclose an offset.
+    pub fn tool_at(&self, offset: usize) -> Option<&ToolRef>
+    {
+        let mut found = None;
+        let bodies = self.program.statements.iter().map(|stmt| (&stmt.flow, &stmt.branches))
+            .chain(self.program.defs.iter().map(|def| (&def.flow, &def.branches)));
+        for (flow, branches) in bodies {
+            tool_in_flow(flow, offset, &mut found);
+            for branch in branches.iter().flatten() {
+                if let Target::Flow { flow, .. } = &branch.target {
+                    tool_in_flow(flow, offset, &mut found);
+                }
+            }
+        }
+
+        found
+    }
+
+    /// Span of a tool's name, just after its opening bracket.
+    pub fn tool_name_span(&self, tool: &ToolRef) -> Span
+    {
+        let inner = &self.text[tool.span.start + 1..tool.span.end];
+        let start = tool.span.start + 1 + inner.len() - inner.trim_start().len();
+        let len = inner.trim_start()
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(0);
+
+        Span { start, end: start + len, source: 0 }
+    }
+
+    /// Byte offset of an editor position, positions are counted in
+    /// UTF-16 code units.
+    pub fn offset(&self, position: Position) -> usize
+    {
+        let mut offset = 0;
+        for (n, line) in self.text.split_inclusive('\n').enumerate() {
+            if n == position.line as usize {
+                let mut units = 0;
+                for (i, c) in line.char_indices() {
+                    if units >= position.character as usize || c == '\n' {
+                        return offset + i
+                    }
+                    units += c.len_utf16();
+                }
+                return offset + line.len()
+            }
+            offset += line.len();
+        }
+
+        self.text.len()
+    }
+
+    pub fn position(&self, offset: usize) -> Position
+    {
+        let before = &self.text[..offset.min(self.text.len())];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line = before.matches('\n').count();
+        let character = before[line_start..].chars().map(char::len_utf16).sum::<usize>();
+
+        Position::new(line as u32, character as u32)
+    }
+
+    pub fn range(&self, span: Span) -> Range
+    {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+}
+
+/// Occurrences in the flow and branches of a statement or definition.
+fn body_occurrences(flow: &Flow, branches: Option<&[Branch]>, found: &mut Vec<Occurrence>)
+{
+    flow_occurrences(flow, found);
+    for branch in branches.into_iter().flatten() {
+        match &branch.target {
+            Target::Variable(name, span) => {
+                found.push(Occurrence { name: *name, span: *span, binding: true });
+            }
+            Target::Flow { flow, variable } => {
+                flow_occurrences(flow, found);
+                if let Some((name, span)) = variable {
+                    found.push(Occurrence { name: *name, span: *span, binding: true });
+                }
+            }
+        }
+    }
+}
+
+fn flow_occurrences(flow: &Flow, found: &mut Vec<Occurrence>)
+{
+    for item in &flow.items {
+        match item {
+            FlowItem::Variable(name, span) => {
+                found.push(Occurrence { name: *name, span: *span, binding: false });
+            }
+            FlowItem::Tool(tool) => {
+                for arg in &tool.args {
+                    let value = match arg {
+                        ToolArg::Keyword { value, .. } => value,
+                        ToolArg::Positional(value, _) => value,
+                    };
+                    match value {
+                        ArgValue::Flow(flow) => flow_occurrences(flow, found),
+                        ArgValue::Ident(name, span) => {
+                            found.push(Occurrence { name: intern(name), span: *span, binding: false });
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn tool_in_flow<'a>(flow: &'a Flow, offset: usize, found: &mut Option<&'a ToolRef>)
+{
+    for item in &flow.items {
+        let FlowItem::Tool(tool) = item else { continue };
+        if tool.span.start <= offset && offset < tool.span.end {
+            *found = Some(tool);
+            for arg in &tool.args {
+                if let ToolArg::Keyword { value: ArgValue::Flow(flow), .. }
+                    | ToolArg::Positional(ArgValue::Flow(flow), _) = arg
+                {
+                    tool_in_flow(flow, offset, found);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{Client, LanguageServer, LspService, Server};
+
+use anvil_context::resolve;
+use anvil_runtime::{completions, tool_help, tool_spec};
+
+mod document;
+
+use document::Document;
+
+
+/// Language server for Anvil scripts, speaking LSP over stdio.
+#[tokio::main]
+async fn main()
+{
+    let (service, socket) = LspService::new(|client| Backend { client, documents: Default::default() });
+    Server::new(tokio::io::stdin(), tokio::io::stdout(), socket)
+        .serve(service)
+        .await;
+}
+
+struct Backend {
+    client: Client,
+    documents: Mutex<HashMap<Url, Document>>,
+}
+
+impl Backend {
+    /// Parse a changed script and publish its syntax errors.
+    async fn update(&self, uri: Url, text: String, version: i32)
+    {
+        let document = Document::parse(text);
+        let diagnostics = document.errors.iter()
+            .map(|e| tower_lsp::lsp_types::Diagnostic {
+                range: document.range(e.span),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("anvil".to_string()),
+                message: e.message.clone(),
+                ..Default::default()
+            })
+            .collect();
+
+        self.documents.lock().unwrap().insert(uri.clone(), document);
+        self.client.publish_diagnostics(uri, diagnostics, Some(version)).await;
+    }
+
+    /// Run `f` on an open document, None if it isn't open.
+    fn with_document<T>(&self, uri: &Url, f: impl FnOnce(&Document) -> Option<T>) -> Option<T>
+    {
+        self.documents.lock().unwrap().get(uri).and_then(f)
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult>
+    {
+        let capabilities = ServerCapabilities {
+            text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+            completion_provider: Some(CompletionOptions {
+                trigger_characters: Some(vec!["[".to_string()]),
+                ..Default::default()
+            }),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            definition_provider: Some(OneOf::Left(true)),
+            references_provider: Some(OneOf::Left(true)),
+            ..Default::default()
+        };
+
+        Ok(InitializeResult {
+            capabilities,
+            server_info: Some(ServerInfo {
+                name: "anvil-lsp".to_string(),
+                version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            }),
+        })
+    }
+
+    async fn shutdown(&self) -> Result<()>
+    {
+        Ok(())
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams)
+    {
+        let doc = params.text_document;
+        self.update(doc.uri, doc.text, doc.version).await;
+    }
+
+    async fn did_change(&self, mut params: DidChangeTextDocumentParams)
+    {
+        // Full sync, the last change holds the whole text
+        if let Some(change) = params.content_changes.pop() {
+            let doc = params.text_document;
+            self.update(doc.uri, change.text, doc.version).await;
+        }
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams)
+    {
+        self.documents.lock().unwrap().remove(&params.text_document.uri);
+        self.client.publish_diagnostics(params.text_document.uri, vec![], None).await;
+    }
+
+    /// Tool names after '[', keyword arguments within a tool's brackets
+    /// and the variables bound in the script.
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>>
+    {
+        let pos = params.text_document_position;
+        let items = self.with_document(&pos.text_document.uri, |doc| {
+            let text = &doc.text[..doc.offset(pos.position)];
+            let start = text
+                .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .map(|i| i + 1)
+                .unwrap_or(0);
+
+            let mut vars = doc.occurrences().into_iter()
+                .filter(|o| o.binding)
+                .map(|o| resolve(o.name).to_string())
+                .collect::<Vec<_>>();
+            vars.sort();
+            vars.dedup();
+            let word = &text[start..];
+            // Nothing has run to know the columns of
+            let mut candidates = completions(text, word, &vars, |_| vec![])
+                .unwrap_or_else(|| vars.clone())
+                .into_iter()
+                .filter(|c| c.starts_with(word))
+                .collect::<Vec<_>>();
+            candidates.sort();
+            candidates.dedup();
+
+            let tool = doc.tool_at(start).map(|tool| resolve(tool.name));
+            let items = candidates.into_iter()
+                .map(|label| completion_item(label, tool, &vars))
+                .collect::<Vec<_>>();
+            Some(items)
+        });
+
+        Ok(items.map(CompletionResponse::Array))
+    }
+
+    /// Help for the tool under the cursor.
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>>
+    {
+        let pos = params.text_document_position_params;
+        let hover = self.with_document(&pos.text_document.uri, |doc| {
+            let offset = doc.offset(pos.position);
+            let tool = doc.tool_at(offset)?;
+            let span = doc.tool_name_span(tool);
+            if offset < span.start || offset > span.end {
+                return None
+            }
+
+            let help = tool_help(resolve(tool.name)).ok()?;
+            Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: format!("```\n{help}\n```"),
+                }),
+                range: Some(doc.range(span)),
+            })
+        });
+
+        Ok(hover)
+    }
+
+    /// Where the variable under the cursor was last bound before it,
+    /// or first bound if only later.
+    async fn goto_definition(&self, params: GotoDefinitionParams) -> Result<Option<GotoDefinitionResponse>>
+    {
+        let pos = params.text_document_position_params;
+        let uri = pos.text_document.uri;
+        let location = self.with_document(&uri, |doc| {
+            let offset = doc.offset(pos.position);
+            let name = doc.occurrence_at(offset)?.name;
+            let bindings = doc.occurrences().into_iter()
+                .filter(|o| o.binding && o.name == name)
+                .collect::<Vec<_>>();
+            let binding = bindings.iter()
+                .rfind(|o| o.span.start <= offset)
+                .or(bindings.first())?;
+
+            Some(Location::new(uri.clone(), doc.range(binding.span)))
+        });
+
+        Ok(location.map(GotoDefinitionResponse::Scalar))
+    }
+
+    /// Every use of the variable under the cursor and, if asked for,
+    /// its bindings.
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>>
+    {
+        let pos = params.text_document_position;
+        let uri = pos.text_document.uri;
+        let declarations = params.context.include_declaration;
+        let locations = self.with_document(&uri, |doc| {
+            let name = doc.occurrence_at(doc.offset(pos.position))?.name;
+            let locations = doc.occurrences().into_iter()
+                .filter(|o| o.name == name && (declarations || !o.binding))
+                .map(|o| Location::new(uri.clone(), doc.range(o.span)))
+                .collect();
+            Some(locations)
+        });
+
+        Ok(locations)
+    }
+}
+
+/// Completion item for a candidate, described from the spec of the
+/// tool it names or the tool whose arguments it's in.
+fn completion_item(label: String, tool: Option<&str>, vars: &[String]) -> CompletionItem
+{
+    let (kind, detail) = if let Some(name) = label.strip_suffix('=') {
+        let arg = tool.and_then(tool_spec).and_then(|spec| spec.keyword(name));
+        (CompletionItemKind::PROPERTY, arg.map(|arg| arg.description.to_string()))
+    } else if vars.contains(&label) {
+        (CompletionItemKind::VARIABLE, None)
+    } else if let Some(spec) = tool_spec(&label) {
+        (CompletionItemKind::FUNCTION, Some(spec.description.to_string()))
+    } else {
+        (CompletionItemKind::FIELD, None)
+    };
+
+    CompletionItem { label, kind: Some(kind), detail, ..Default::default() }
+}
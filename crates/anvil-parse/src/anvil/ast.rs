@@ -1,10 +1,133 @@
 use std::fmt;
 
-use anvil_context::Symbol;
+use anvil_context::{resolve, Symbol};
 
-#[derive(Debug)]
+pub use crate::diagnostic::Span;
+use crate::anvil::strings::quote;
+
+#[derive(Debug, Default)]
 pub struct Program {
+    /// Parameters declared at the top of the script
+    pub params: Option<ParamBlock>,
+
+    /// Scripts imported, see import::load_imports for merging them
+    pub imports: Vec<Import>,
+
     pub statements: Vec<Statement>,
+
+    /// Tools defined by the script, planned where they're used
+    pub defs: Vec<Def>,
+
+    /// Comments in source order, kept for formatting
+    pub comments: Vec<Comment>,
+}
+
+/// A '#' comment, running to the end of its line.
+#[derive(Clone, Debug)]
+pub struct Comment {
+    /// Text of the comment including the '#'
+    pub text: String,
+
+    /// Location in the source
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct ParamBlock {
+    pub params: Vec<Param>,
+
+    /// Location in the source
+    pub span: Span,
+}
+
+/// An `import` of another script.
+#[derive(Debug)]
+pub struct Import {
+    /// Path of the script, relative to the one importing it
+    pub path: String,
+
+    /// Name qualifying the variables the script binds
+    pub alias: Option<(Symbol, Span)>,
+
+    /// Location in the source
+    pub span: Span,
+}
+
+/// A script parameter, given a value when the script is run or taking
+/// its default.
+#[derive(Debug)]
+pub struct Param {
+    pub name: Symbol,
+    pub ty: ParamType,
+    pub default: Option<ArgValue>,
+
+    /// Location in the source
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    /// A calendar date, YYYY-MM-DD
+    Date,
+}
+
+impl ParamType {
+    pub fn from_name(name: &str) -> Option<ParamType>
+    {
+        let ty = match name {
+            "string"  => ParamType::String,
+            "integer" => ParamType::Integer,
+            "float"   => ParamType::Float,
+            "boolean" => ParamType::Boolean,
+            "date"    => ParamType::Date,
+            _ => return None,
+        };
+
+        Some(ty)
+    }
+}
+
+/// A tool defined with `def` as a flow of others.
+#[derive(Clone, Debug)]
+pub struct Def {
+    pub name: Symbol,
+
+    /// Parameters, each standing for an argument wherever it's used
+    /// as a value or flow in the definition, with its default
+    pub params: Vec<(Symbol, Option<ArgValue>)>,
+
+    /// Named outputs, each bound by one of the branches
+    pub outputs: Vec<Symbol>,
+
+    pub flow: Flow,
+    pub branches: Option<Vec<Branch>>,
+
+    /// Location in the source
+    pub span: Span,
+}
+
+impl Def {
+    /// What comes before the '=', e.g. `def clean(cols, value = 0)`.
+    pub fn signature(&self) -> String
+    {
+        let params = self.params.iter()
+            .map(|(name, default)| match default {
+                Some(default) => format!("{} = {default}", resolve(*name)),
+                None => resolve(*name).to_string(),
+            })
+            .collect::<Vec<_>>();
+        let mut signature = format!("def {}({})", resolve(self.name), params.join(", "));
+        if !self.outputs.is_empty() {
+            let outputs = self.outputs.iter().map(|name| resolve(*name)).collect::<Vec<_>>();
+            signature += &format!(" -> ({})", outputs.join(", "));
+        }
+
+        signature
+    }
 }
 
 #[derive(Debug)]
@@ -16,40 +139,57 @@ pub struct Statement {
     pub branches: Option<Vec<Branch>>,
 
     /// Optional variable binding for the entire statement
-    pub variable: Option<Symbol>,
+    pub variable: Option<(Symbol, Span)>,
+
+    /// Location in the source
+    pub span: Span,
 }
 
 #[derive(Clone, Debug)]
 pub struct Flow {
     /// Linear sequence of tools/variables
     pub items: Vec<FlowItem>,
+
+    /// Location in the source
+    pub span: Span,
+}
+
+impl Flow {
+    /// Flow reading a single variable, e.g. one named by a tool argument.
+    pub fn variable(name: Symbol, span: Span) -> Flow
+    {
+        Flow { items: vec![FlowItem::Variable(name, span)], span }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub enum FlowItem {
     Tool(ToolRef),
-    Variable(Symbol),
+    Variable(Symbol, Span),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Branch {
     /// Branch name (e.g. "true", "false", "joined")
     pub name: Symbol,
 
     /// Where this branch sends its data
     pub target: Target,
+
+    /// Location in the source
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Target {
     /// Execute a flow, optionally binding its result
     Flow {
         flow: Flow,
-        variable: Option<Symbol>,
+        variable: Option<(Symbol, Span)>,
     },
 
     /// Directly bind to an existing variable
-    Variable(Symbol),
+    Variable(Symbol, Span),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -67,19 +207,247 @@ pub struct ToolRef {
     pub id: ToolId,
     pub name: Symbol,
     pub args: Vec<ToolArg>,
+    /// Location in the source
+    pub span: Span,
 }
 
 #[derive(Clone, Debug)]
 pub enum ToolArg {
-    Keyword { ident: Symbol, value: ArgValue },
-    Positional(ArgValue),
+    Keyword { ident: Symbol, value: ArgValue, span: Span },
+    Positional(ArgValue, Span),
+}
+
+impl ToolArg {
+    pub fn value(&self) -> &ArgValue
+    {
+        match self {
+            ToolArg::Keyword { value, .. } => value,
+            ToolArg::Positional(value, _) => value,
+        }
+    }
+
+    pub fn value_mut(&mut self) -> &mut ArgValue
+    {
+        match self {
+            ToolArg::Keyword { value, .. } => value,
+            ToolArg::Positional(value, _) => value,
+        }
+    }
+
+    /// Location in the source
+    pub fn span(&self) -> Span
+    {
+        match self {
+            ToolArg::Keyword { span, .. } => *span,
+            ToolArg::Positional(_, span) => *span,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub enum ArgValue {
     Flow(Flow),
-    Ident(String),
+    Ident(String, Span),
     Boolean(bool),
     Integer(i64),
+    Float(f64),
     String(String),
+    Null,
+    List(Vec<ArgValue>),
+    /// Entries in the order they were written
+    Map(Vec<(String, ArgValue)>),
+}
+
+
+// The AST prints back to source on a single line, each construct in
+// the form it's parsed from. Comments aren't part of it, see
+// format::format_script for a layout which keeps them.
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        if let Some(params) = &self.params {
+            writeln!(f, "{params}")?;
+        }
+        for import in &self.imports {
+            writeln!(f, "{import}")?;
+        }
+        // Definitions and statements in the order they were written
+        let mut items = self.defs.iter()
+            .map(|def| (def.span.start, def.to_string()))
+            .chain(self.statements.iter().map(|stmt| (stmt.span.start, stmt.to_string())))
+            .collect::<Vec<_>>();
+        items.sort_by_key(|(start, _)| *start);
+        for (_, item) in items {
+            writeln!(f, "{item}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for ParamBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        let params = self.params.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        write!(f, "params {{{}}}", params.join(", "))
+    }
+}
+
+impl fmt::Display for Import {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "import {}", quote(&self.path))?;
+        if let Some((alias, _)) = self.alias {
+            write!(f, " as {}", resolve(alias))?;
+        }
+
+        write!(f, ";")
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{}: {}", resolve(self.name), self.ty)?;
+        if let Some(default) = &self.default {
+            write!(f, " = {default}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        let name = match self {
+            ParamType::String  => "string",
+            ParamType::Integer => "integer",
+            ParamType::Float   => "float",
+            ParamType::Boolean => "boolean",
+            ParamType::Date    => "date",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for Def {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{} = {}", self.signature(), self.flow)?;
+        if let Some(branches) = &self.branches {
+            let branches = branches.iter().map(|b| b.to_string()).collect::<Vec<_>>();
+            write!(f, ": {}", branches.join(", "))?;
+        }
+
+        write!(f, ";")
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{}", self.flow)?;
+        if let Some(branches) = &self.branches {
+            let branches = branches.iter().map(|b| b.to_string()).collect::<Vec<_>>();
+            write!(f, ": {}", branches.join(", "))?;
+        }
+        if let Some((name, _)) = self.variable {
+            write!(f, " > {}", resolve(name))?;
+        }
+
+        write!(f, ";")
+    }
+}
+
+impl fmt::Display for Flow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        let items = self.items.iter().map(|item| item.to_string()).collect::<Vec<_>>();
+        write!(f, "{}", items.join(" | "))
+    }
+}
+
+impl fmt::Display for FlowItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            FlowItem::Tool(tool) => write!(f, "{tool}"),
+            FlowItem::Variable(name, _) => write!(f, "{}", resolve(*name)),
+        }
+    }
+}
+
+impl fmt::Display for Branch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{} => {}", resolve(self.name), self.target)
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            Target::Variable(name, _) => write!(f, "{}", resolve(*name)),
+            Target::Flow { flow, variable: None } => write!(f, "{flow}"),
+            Target::Flow { flow, variable: Some((name, _)) } => write!(f, "{flow} > {}", resolve(*name)),
+        }
+    }
+}
+
+impl fmt::Display for ToolRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        if self.args.is_empty() {
+            write!(f, "[{}]", resolve(self.name))
+        } else {
+            let args = self.args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+            write!(f, "[{}: {}]", resolve(self.name), args.join(", "))
+        }
+    }
+}
+
+impl fmt::Display for ToolArg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            ToolArg::Keyword { ident, value, .. } => write!(f, "{}={value}", resolve(*ident)),
+            ToolArg::Positional(value, _) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl fmt::Display for ArgValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            ArgValue::Flow(flow) => write!(f, "({flow})"),
+            ArgValue::Ident(name, _) => write!(f, "{name}"),
+            ArgValue::Boolean(b) => write!(f, "{b}"),
+            ArgValue::Integer(n) => write!(f, "{n}"),
+            // Debug keeps the fraction of whole floats, 1.0 not 1
+            ArgValue::Float(n) => write!(f, "{n:?}"),
+            ArgValue::String(s) => write!(f, "{}", quote(s)),
+            ArgValue::Null => write!(f, "null"),
+            ArgValue::List(values) => {
+                let values = values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+                write!(f, "[{}]", values.join(", "))
+            }
+            ArgValue::Map(entries) => {
+                let entries = entries.iter()
+                    .map(|(key, v)| if is_name(key) { format!("{key}: {v}") } else { format!("{}: {v}", quote(key)) })
+                    .collect::<Vec<_>>();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+        }
+    }
+}
+
+/// Whether a map key can be written without quotes.
+fn is_name(key: &str) -> bool
+{
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
 }
@@ -0,0 +1,379 @@
+use anyhow::{anyhow, bail, Result};
+
+use anvil_context::resolve;
+
+use crate::anvil::ast::*;
+use crate::anvil::parse::{build_program, ASTBuilder};
+
+
+/// Columns a line may take before what's on it is split over several.
+const WIDTH: usize = 80;
+
+/// Columns a tab is counted as.
+const TAB: usize = 4;
+
+/// Pipelines with more items than this get one item per line.
+const PIPELINE: usize = 3;
+
+/// Tools with each argument on its own line when they have more than one.
+const KEYWORD_LAYOUT: [&str; 2] = ["join", "project"];
+
+/// Format a script in canonical style, keeping its comments. The
+/// result is checked to parse back to the same statements and comments.
+pub fn format_script(source: &str) -> Result<String>
+{
+    let program = build_program(&mut ASTBuilder::new(), source)?;
+    let formatted = Formatter { source, comments: &program.comments }.program(&program);
+
+    let reparsed = build_program(&mut ASTBuilder::new(), &formatted)
+        .map_err(|e| anyhow!("formatted script doesn't parse: {e:#}"))?;
+    let comments = |p: &Program| p.comments.iter().map(|c| c.text.clone()).collect::<Vec<_>>();
+    if reparsed.to_string() != program.to_string() || comments(&reparsed) != comments(&program) {
+        bail!("formatting changed the script")
+    }
+
+    Ok(formatted)
+}
+
+/// A line of output and where what's on it came from in the source,
+/// which places the comments around it.
+struct Line {
+    indent: String,
+    text: String,
+    span: Span,
+    /// First line of a statement
+    top: bool,
+}
+
+impl Line {
+    fn new(indent: &str, text: String, span: Span) -> Line
+    {
+        Line { indent: indent.to_string(), text, span, top: false }
+    }
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    comments: &'a [Comment],
+}
+
+impl Formatter<'_> {
+    fn program(&self, program: &Program) -> String
+    {
+        let mut lines = vec![];
+        if let Some(params) = &program.params {
+            lines.extend(self.params(params));
+            lines[0].top = true;
+        }
+        for import in &program.imports {
+            let mut line = Line::new("", import.to_string(), import.span);
+            line.top = true;
+            lines.push(line);
+        }
+
+        // Definitions and statements in the order they were written
+        let mut items = program.defs.iter()
+            .map(|def| (def.span.start, self.def(def)))
+            .chain(program.statements.iter().map(|stmt| (stmt.span.start, self.statement(stmt))))
+            .collect::<Vec<_>>();
+        items.sort_by_key(|(start, _)| *start);
+        for (_, mut item) in items {
+            item[0].top = true;
+            lines.extend(item);
+        }
+
+        self.weave(lines)
+    }
+
+    /// The parameter block with each parameter on a line of its own.
+    fn params(&self, block: &ParamBlock) -> Vec<Line>
+    {
+        if block.params.is_empty() {
+            return vec![Line::new("", block.to_string(), block.span)]
+        }
+
+        let start = Span { start: block.span.start, end: block.span.start + 1, ..block.span };
+        let mut lines = vec![Line::new("", "params {".to_string(), start)];
+        let last = block.params.len() - 1;
+        for (i, param) in block.params.iter().enumerate() {
+            let comma = if i < last { "," } else { "" };
+            lines.push(Line::new("\t", format!("{param}{comma}"), param.span));
+        }
+        let end = Span { start: block.span.end - 1, end: block.span.end, ..block.span };
+        lines.push(Line::new("", "}".to_string(), end));
+
+        lines
+    }
+
+    fn statement(&self, stmt: &Statement) -> Vec<Line>
+    {
+        let binding = stmt.variable
+            .map(|(name, _)| format!(" > {}", resolve(name)))
+            .unwrap_or_default();
+
+        self.body("", "", &stmt.flow, stmt.branches.as_deref(), &binding, stmt.span)
+    }
+
+    /// A definition on one line if it's short, otherwise its signature
+    /// followed by its flow laid out as a statement, indented.
+    fn def(&self, def: &Def) -> Vec<Line>
+    {
+        let signature = def.signature();
+        let lines = self.body("", &format!("{signature} = "), &def.flow, def.branches.as_deref(), "", def.span);
+        if lines.len() == 1 {
+            return lines
+        }
+
+        let start = Span { start: def.span.start, end: def.flow.span.start, ..def.span };
+        let mut lines = self.body("\t", "", &def.flow, def.branches.as_deref(), "", def.span);
+        lines[0].span.start = def.flow.span.start;
+        lines.insert(0, Line::new("", format!("{signature} ="), start));
+
+        lines
+    }
+
+    /// A flow after `lead` on one line if it's short, otherwise its
+    /// pipeline split one item per line and its branches one per line
+    /// with their arrows aligned, then any binding and the ';'.
+    fn body(&self, indent: &str, lead: &str, flow: &Flow, branches: Option<&[Branch]>, binding: &str, span: Span) -> Vec<Line>
+    {
+        let Some(branches) = branches else {
+            let mut lines = self.flow(flow, indent, lead, indent, &format!("{binding};"));
+            lines[0].span.start = span.start;
+            lines.last_mut().unwrap().span.end = span.end;
+            return lines
+        };
+
+        let mut lines = self.flow(flow, indent, lead, indent, ":");
+        lines[0].span.start = span.start;
+        let (inner, cont) = (format!("{indent}\t"), format!("{indent}\t\t"));
+        let pad = branches.iter().map(|b| resolve(b.name).len()).max().unwrap_or(0);
+        for (i, branch) in branches.iter().enumerate() {
+            let lead = format!("{:pad$} => ", resolve(branch.name));
+            let suffix = if i + 1 < branches.len() { ",".to_string() } else { format!("{binding};") };
+            let mut branch_lines = match &branch.target {
+                Target::Variable(name, span) => {
+                    vec![Line::new(&inner, format!("{lead}{}{suffix}", resolve(*name)), *span)]
+                }
+                Target::Flow { flow, variable } => {
+                    let bound = variable
+                        .map(|(name, _)| format!(" > {}", resolve(name)))
+                        .unwrap_or_default();
+                    self.flow(flow, &inner, &lead, &cont, &format!("{bound}{suffix}"))
+                }
+            };
+            branch_lines[0].span.start = branch.span.start;
+            branch_lines.last_mut().unwrap().span.end = branch.span.end;
+            lines.extend(branch_lines);
+        }
+        lines.last_mut().unwrap().span.end = span.end;
+
+        lines
+    }
+
+    /// A flow after `lead` on a line indented by `indent` and followed
+    /// by `suffix`. Split, its items after the first go on lines of
+    /// their own indented by `cont`.
+    fn flow(&self, flow: &Flow, indent: &str, lead: &str, cont: &str, suffix: &str) -> Vec<Line>
+    {
+        let flat = format!("{lead}{flow}{suffix}");
+        let multiline = flow.items.iter()
+            .any(|item| matches!(item, FlowItem::Tool(tool) if multiline(tool)));
+        if flow.items.len() <= PIPELINE && !multiline && fits(indent, &flat) && !self.encloses(flow.span) {
+            return vec![Line::new(indent, flat, flow.span)]
+        }
+
+        let mut lines = vec![];
+        let last = flow.items.len() - 1;
+        for (i, item) in flow.items.iter().enumerate() {
+            let (indent, lead) = if i == 0 { (indent, lead) } else { (cont, "| ") };
+            let suffix = if i == last { suffix } else { "" };
+            match item {
+                FlowItem::Tool(tool) => lines.extend(self.tool(tool, indent, lead, suffix)),
+                FlowItem::Variable(name, span) => {
+                    lines.push(Line::new(indent, format!("{lead}{}{suffix}", resolve(*name)), *span))
+                }
+            }
+        }
+
+        lines
+    }
+
+    /// A tool on one line if it fits, otherwise with each argument on
+    /// a line of its own, indented past the opening bracket.
+    fn tool(&self, tool: &ToolRef, indent: &str, lead: &str, suffix: &str) -> Vec<Line>
+    {
+        let flat = format!("{lead}{tool}{suffix}");
+        if tool.args.is_empty() || (!multiline(tool) && fits(indent, &flat) && !self.encloses(tool.span)) {
+            return vec![Line::new(indent, flat, tool.span)]
+        }
+
+        let start = Span { start: tool.span.start, end: tool.span.start + 1, ..tool.span };
+        let mut lines = vec![Line::new(indent, format!("{lead}[{}:", resolve(tool.name)), start)];
+
+        let inner = format!("{indent}{}", " ".repeat(lead.len() + 2));
+        let last = tool.args.len() - 1;
+        for (i, arg) in tool.args.iter().enumerate() {
+            let comma = if i < last { "," } else { "" };
+            let ArgValue::Flow(flow) = arg.value() else {
+                lines.push(Line::new(&inner, format!("{arg}{comma}"), arg.span()));
+                continue
+            };
+
+            // Subflows split with their pipes under the first item
+            let lead = match arg {
+                ToolArg::Keyword { ident, .. } => format!("{}=(", resolve(*ident)),
+                ToolArg::Positional(..) => "(".to_string(),
+            };
+            let cont = format!("{inner}{}", " ".repeat(lead.len()));
+            let mut arg_lines = self.flow(flow, &inner, &lead, &cont, &format!("){comma}"));
+            arg_lines[0].span.start = arg.span().start;
+            arg_lines.last_mut().unwrap().span.end = arg.span().end;
+            lines.extend(arg_lines);
+        }
+
+        let end = Span { start: tool.span.end - 1, end: tool.span.end, ..tool.span };
+        let indent = format!("{indent}{}", " ".repeat(lead.len()));
+        lines.push(Line::new(&indent, format!("]{suffix}"), end));
+
+        lines
+    }
+
+    /// Whether a comment sits within `span`, splitting what's there
+    /// so the comment can go between its lines.
+    fn encloses(&self, span: Span) -> bool
+    {
+        self.comments.iter().any(|c| span.start < c.span.start && c.span.start < span.end)
+    }
+
+    /// The lines with the comments put back where they were relative
+    /// to them: on the line they ended or otherwise on lines of their
+    /// own before the next. A blank line between statements is kept.
+    fn weave(&self, lines: Vec<Line>) -> String
+    {
+        let mut out = String::new();
+        let mut comments = self.comments.iter().peekable();
+        let mut last = 0;
+
+        for (n, line) in lines.iter().enumerate() {
+            let indent = if line.top { "" } else { &line.indent };
+            while let Some(c) = comments.next_if(|c| c.span.start < line.span.start) {
+                self.separate(&mut out, last, c.span.start, line.top);
+                out += &format!("{indent}{}\n", c.text);
+                last = c.span.end;
+            }
+
+            self.separate(&mut out, last, line.span.start, line.top);
+            out += &format!("{}{}", line.indent, line.text);
+            last = last.max(line.span.end);
+
+            let next = lines.get(n + 1).map(|l| l.span.start).unwrap_or(usize::MAX);
+            let trailing = |c: &&Comment| {
+                line.span.end <= c.span.start && c.span.start < next
+                    && !self.source[line.span.end..c.span.start].contains('\n')
+            };
+            if let Some(c) = comments.next_if(trailing) {
+                out += &format!(" {}", c.text);
+                last = c.span.end;
+            }
+            out.push('\n');
+        }
+
+        for c in comments {
+            self.separate(&mut out, last, c.span.start, true);
+            out += &format!("{}\n", c.text);
+            last = c.span.end;
+        }
+
+        out
+    }
+
+    /// Add a blank line at the top level if there was one in the
+    /// source between `from` and `to`.
+    fn separate(&self, out: &mut String, from: usize, to: usize, top: bool)
+    {
+        if top && !out.is_empty() && from <= to && self.source[from..to].matches('\n').count() > 1 {
+            out.push('\n');
+        }
+    }
+}
+
+/// Whether a tool goes over several lines whatever its width, given
+/// its name or a subflow which does.
+fn multiline(tool: &ToolRef) -> bool
+{
+    let keyword_layout = tool.args.len() > 1 && KEYWORD_LAYOUT.contains(&resolve(tool.name));
+    keyword_layout || tool.args.iter().any(|arg| match arg.value() {
+        ArgValue::Flow(flow) => flow.items.iter()
+            .any(|item| matches!(item, FlowItem::Tool(tool) if multiline(tool))),
+        _ => false,
+    })
+}
+
+fn fits(indent: &str, text: &str) -> bool
+{
+    let width = indent.chars().chain(text.chars())
+        .map(|c| if c == '\t' { TAB } else { 1 })
+        .sum::<usize>();
+
+    width <= WIDTH
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn program(source: &str) -> String
+    {
+        build_program(&mut ASTBuilder::new(), source).unwrap().to_string()
+    }
+
+    #[test]
+    fn canonical_style()
+    {
+        let source = "params { n: integer = 5 }\n\
+            # leading\n\
+            [input: './a.csv']|[filter:'$x > ${n}']:  true => t, false=>[print];  # trailing\n\
+            def top(n=10) = [sort: 'x'] | [limit: n];\n\
+            t | [top: n=3] > u;\n";
+        let expected = "params {\n\
+            \tn: integer = 5\n\
+            }\n\
+            # leading\n\
+            [input: './a.csv'] | [filter: '$x > ${n}']:\n\
+            \ttrue  => t,\n\
+            \tfalse => [print]; # trailing\n\
+            def top(n = 10) = [sort: 'x'] | [limit: n];\n\
+            t | [top: n=3] > u;\n";
+        assert_eq!(format_script(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn long_pipelines_split()
+    {
+        let formatted = format_script("a | [distinct] | [sort: 'x'] | [limit: 5] | [print];").unwrap();
+        assert_eq!(formatted, "a\n| [distinct]\n| [sort: 'x']\n| [limit: 5]\n| [print];\n");
+    }
+
+    #[test]
+    fn examples_round_trip()
+    {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../examples");
+        for entry in std::fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            let source = std::fs::read_to_string(&path).unwrap();
+
+            let formatted = format_script(&source).unwrap_or_else(|e| panic!("{}: {e:#}", path.display()));
+            assert_eq!(program(&formatted), program(&source), "{}", path.display());
+            assert_eq!(format_script(&formatted).unwrap(), formatted, "{} isn't idempotent", path.display());
+        }
+    }
+
+    #[test]
+    fn syntax_errors_reported()
+    {
+        assert!(format_script("[input: 'a.csv'] |").is_err());
+    }
+}
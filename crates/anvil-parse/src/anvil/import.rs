@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use anvil_context::{intern, resolve, Symbol};
+
+use crate::anvil::ast::*;
+use crate::anvil::parse::{build_program, ASTBuilder};
+use crate::diagnostic::{locate, Diagnostic, Diagnostics, Locate, Sources};
+
+
+/// Parse the scripts a program imports, and those they import, and put
+/// their statements and definitions before its own. Paths are relative to the directory
+/// of the importing script, `path`. The variables bound by an import
+/// with an alias are renamed `alias.name`. A script imported more than
+/// once is only merged the first time, later imports bind its variables
+/// under the names they read them by. Imported scripts are added to
+/// `sources`, which the spans of their statements refer to, so errors
+/// in them render against them.
+pub fn load_imports(builder: &mut ASTBuilder, program: &mut Program, path: &Path, sources: &mut Sources) -> Result<()>
+{
+    let mut imports = Imports { sources, loading: vec![], loaded: HashMap::new() };
+    if let Ok(path) = path.canonicalize() {
+        imports.loading.push(path);
+    }
+
+    load(builder, program, path, &mut imports)
+}
+
+struct Imports<'a> {
+    sources: &'a mut Sources,
+    /// Scripts importing the one being loaded, to detect cycles
+    loading: Vec<PathBuf>,
+    /// Scripts merged so far, with each variable they bind and the
+    /// name it was merged under
+    loaded: HashMap<PathBuf, Vec<(Symbol, Symbol)>>,
+}
+
+/// Load the imports of a script at `path`.
+fn load(builder: &mut ASTBuilder, program: &mut Program, path: &Path, imports: &mut Imports<'_>) -> Result<()>
+{
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut statements = vec![];
+    let mut defs = vec![];
+
+    for import in &program.imports {
+        let file = dir.join(&import.path);
+        let canonical = file.canonicalize()
+            .map_err(|e| anyhow!("can't read imported script '{}': {e}", import.path))
+            .at(import.span)?;
+        if let Some(i) = imports.loading.iter().position(|p| *p == canonical) {
+            let cycle = imports.loading[i..].iter()
+                .chain([&canonical])
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>();
+            return Err(anyhow!("import cycle: {}", cycle.join(" -> "))).at(import.span)
+        }
+        let alias = import.alias.map(|(alias, _)| alias);
+        if let Some(bound) = imports.loaded.get(&canonical) {
+            for (name, merged) in bound {
+                let name = qualify(alias, *name);
+                if name != *merged {
+                    statements.push(rebind(*merged, name, import.span));
+                }
+            }
+            continue
+        }
+
+        let source = std::fs::read_to_string(&file)
+            .map_err(|e| anyhow!("can't read imported script '{}': {e}", import.path))
+            .at(import.span)?;
+        let id = imports.sources.add(&file.display().to_string(), &source);
+        let in_source = |span: Span| Span { source: id, ..span };
+        let mut imported = build_program(builder, &source)
+            .map_err(|e| source_error(e, id, import.span))?;
+        if let Some(params) = &imported.params {
+            let message = "imported scripts can't declare parameters".to_string();
+            return Err(Diagnostic { message, span: in_source(params.span) }.into())
+        }
+        for import in &mut imported.imports {
+            import.span = in_source(import.span);
+        }
+        for stmt in &mut imported.statements {
+            relocate_statement(stmt, &in_source, &|name| name);
+        }
+        for def in &mut imported.defs {
+            relocate_def(def, &in_source, &|name| name);
+        }
+
+        imports.loading.push(canonical.clone());
+        load(builder, &mut imported, &file, imports)?;
+        imports.loading.pop();
+
+        let mut bound = imported.statements.iter().flat_map(bindings).collect::<Vec<_>>();
+        bound.sort();
+        bound.dedup();
+        if alias.is_some() {
+            let rename = |name: Symbol| if bound.contains(&name) { qualify(alias, name) } else { name };
+            for stmt in &mut imported.statements {
+                relocate_statement(stmt, &|span| span, &rename);
+            }
+            for def in &mut imported.defs {
+                relocate_def(def, &|span| span, &rename);
+            }
+        }
+        let bound = bound.into_iter().map(|name| (name, qualify(alias, name))).collect();
+        imports.loaded.insert(canonical, bound);
+        statements.append(&mut imported.statements);
+        defs.append(&mut imported.defs);
+    }
+
+    statements.append(&mut program.statements);
+    program.statements = statements;
+    defs.append(&mut program.defs);
+    program.defs = defs;
+
+    Ok(())
+}
+
+/// Variables a statement binds, with '>' or by a branch.
+fn bindings(stmt: &Statement) -> Vec<Symbol>
+{
+    let mut bound = vec![];
+    for branch in stmt.branches.iter().flatten() {
+        match &branch.target {
+            Target::Variable(name, _) => bound.push(*name),
+            Target::Flow { variable: Some((name, _)), .. } => bound.push(*name),
+            Target::Flow { variable: None, .. } => {}
+        }
+    }
+    bound.extend(stmt.variable.map(|(name, _)| name));
+
+    bound
+}
+
+/// A variable bound by an imported script as it's named by an import,
+/// qualified by the import's alias if it has one.
+fn qualify(alias: Option<Symbol>, name: Symbol) -> Symbol
+{
+    match alias {
+        Some(alias) => intern(&format!("{}.{}", resolve(alias), resolve(name))),
+        None => name,
+    }
+}
+
+/// A statement `from > to`, binding a variable merged by an earlier
+/// import under the name a later import reads it by.
+fn rebind(from: Symbol, to: Symbol, span: Span) -> Statement
+{
+    Statement {
+        flow: Flow { items: vec![FlowItem::Variable(from, span)], span },
+        branches: None,
+        variable: Some((to, span)),
+        span,
+    }
+}
+
+/// An error parsing an imported script, its spans in the script with
+/// id `source`. Errors without a location are located at the import.
+fn source_error(error: anyhow::Error, source: usize, import: Span) -> anyhow::Error
+{
+    let in_source = |d: Diagnostic| Diagnostic { message: d.message, span: Span { source, ..d.span } };
+
+    match error.downcast::<Diagnostic>() {
+        Ok(d) => in_source(d).into(),
+        Err(error) => match error.downcast::<Diagnostics>() {
+            Ok(ds) => Diagnostics(ds.0.into_iter().map(in_source).collect()).into(),
+            Err(error) => locate(error, import),
+        },
+    }
+}
+
+/// Map the spans of a statement with `respan` and rename its variables.
+fn relocate_statement(stmt: &mut Statement, respan: &impl Fn(Span) -> Span, rename: &impl Fn(Symbol) -> Symbol)
+{
+    relocate_flow(&mut stmt.flow, respan, rename);
+    for branch in stmt.branches.iter_mut().flatten() {
+        match &mut branch.target {
+            Target::Variable(name, span) => relocate_variable(name, span, respan, rename),
+            Target::Flow { flow, variable } => {
+                relocate_flow(flow, respan, rename);
+                if let Some((name, span)) = variable {
+                    relocate_variable(name, span, respan, rename);
+                }
+            }
+        }
+        branch.span = respan(branch.span);
+    }
+    if let Some((name, span)) = &mut stmt.variable {
+        relocate_variable(name, span, respan, rename);
+    }
+    stmt.span = respan(stmt.span);
+}
+
+/// Map the spans of a definition with `respan` and rename the variables
+/// its flow reads, other than its parameters.
+fn relocate_def(def: &mut Def, respan: &impl Fn(Span) -> Span, rename: &impl Fn(Symbol) -> Symbol)
+{
+    let params = def.params.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    let outputs = def.outputs.clone();
+    let rename = |name: Symbol| if params.contains(&name) || outputs.contains(&name) { name } else { rename(name) };
+
+    relocate_flow(&mut def.flow, respan, &rename);
+    for branch in def.branches.iter_mut().flatten() {
+        match &mut branch.target {
+            Target::Variable(name, span) => relocate_variable(name, span, respan, &rename),
+            Target::Flow { flow, variable } => {
+                relocate_flow(flow, respan, &rename);
+                if let Some((name, span)) = variable {
+                    relocate_variable(name, span, respan, &rename);
+                }
+            }
+        }
+        branch.span = respan(branch.span);
+    }
+    def.span = respan(def.span);
+}
+
+fn relocate_flow(flow: &mut Flow, respan: &impl Fn(Span) -> Span, rename: &impl Fn(Symbol) -> Symbol)
+{
+    for item in &mut flow.items {
+        match item {
+            FlowItem::Variable(name, span) => relocate_variable(name, span, respan, rename),
+            FlowItem::Tool(tool) => {
+                for arg in &mut tool.args {
+                    relocate_value(arg.value_mut(), respan, rename);
+                    match arg {
+                        ToolArg::Keyword { span, .. } => *span = respan(*span),
+                        ToolArg::Positional(_, span) => *span = respan(*span),
+                    }
+                }
+                tool.span = respan(tool.span);
+            }
+        }
+    }
+    flow.span = respan(flow.span);
+}
+
+fn relocate_value(value: &mut ArgValue, respan: &impl Fn(Span) -> Span, rename: &impl Fn(Symbol) -> Symbol)
+{
+    match value {
+        ArgValue::Flow(flow) => relocate_flow(flow, respan, rename),
+        ArgValue::Ident(name, span) => {
+            *name = resolve(rename(intern(name))).to_string();
+            *span = respan(*span);
+        }
+        _ => {}
+    }
+}
+
+fn relocate_variable(name: &mut Symbol, span: &mut Span, respan: &impl Fn(Span) -> Span, rename: &impl Fn(Symbol) -> Symbol)
+{
+    *name = rename(*name);
+    *span = respan(*span);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of scripts for a test, named by it.
+    fn scripts(test: &str, files: &[(&str, &str)]) -> PathBuf
+    {
+        let dir = std::env::temp_dir().join(format!("anvil-import-{test}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, source) in files {
+            std::fs::write(dir.join(name), source).unwrap();
+        }
+        dir
+    }
+
+    /// The statements of `main.anvil` once its imports are loaded.
+    fn load_main(dir: &Path) -> Result<Vec<String>>
+    {
+        let path = dir.join("main.anvil");
+        let mut builder = ASTBuilder::new();
+        let mut program = build_program(&mut builder, &std::fs::read_to_string(&path)?)?;
+        load_imports(&mut builder, &mut program, &path, &mut Sources::default())?;
+
+        Ok(program.statements.iter().map(|stmt| stmt.to_string()).collect())
+    }
+
+    #[test]
+    fn imports_come_first()
+    {
+        let dir = scripts("first", &[
+            ("main.anvil", "import 'lib.anvil';\nusers | [print];\n"),
+            ("lib.anvil", "[input: 'users.csv'] > users;\n"),
+        ]);
+        let statements = load_main(&dir).unwrap();
+        assert_eq!(statements, ["[input: 'users.csv'] > users;", "users | [print];"]);
+    }
+
+    #[test]
+    fn alias_qualifies_bound_variables()
+    {
+        let dir = scripts("alias", &[
+            ("main.anvil", "import 'lib.anvil' as common;\ncommon.users | [print];\n"),
+            ("lib.anvil", "[input: 'users.csv'] > users;\nusers | [count] > n;\nother | [print];\n"),
+        ]);
+        let statements = load_main(&dir).unwrap();
+        assert_eq!(statements, [
+            "[input: 'users.csv'] > common.users;",
+            "common.users | [count] > common.n;",
+            "other | [print];",
+            "common.users | [print];",
+        ]);
+    }
+
+    #[test]
+    fn imported_again_is_rebound()
+    {
+        let dir = scripts("again", &[
+            ("main.anvil", "import 'lib.anvil';\nimport 'lib.anvil' as common;\n"),
+            ("lib.anvil", "[input: 'users.csv'] > users;\n"),
+        ]);
+        let statements = load_main(&dir).unwrap();
+        assert_eq!(statements, ["[input: 'users.csv'] > users;", "users > common.users;"]);
+    }
+
+    #[test]
+    fn import_cycle()
+    {
+        let dir = scripts("cycle", &[
+            ("main.anvil", "import 'a.anvil';\n"),
+            ("a.anvil", "import 'b.anvil';\n"),
+            ("b.anvil", "import 'a.anvil';\n"),
+        ]);
+        let error = load_main(&dir).unwrap_err().to_string();
+        assert!(error.contains("import cycle"), "{error}");
+        assert!(error.contains("a.anvil -> ") && error.ends_with("a.anvil"), "{error}");
+    }
+
+    #[test]
+    fn import_self()
+    {
+        let dir = scripts("self", &[("main.anvil", "import 'main.anvil';\n")]);
+        let error = load_main(&dir).unwrap_err().to_string();
+        assert!(error.contains("import cycle"), "{error}");
+    }
+
+    #[test]
+    fn missing_import()
+    {
+        let dir = scripts("missing", &[("main.anvil", "import 'nowhere.anvil';\n")]);
+        let error = load_main(&dir).unwrap_err().to_string();
+        assert!(error.contains("can't read imported script 'nowhere.anvil'"), "{error}");
+    }
+}
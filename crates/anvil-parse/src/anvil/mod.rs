@@ -5,4 +5,8 @@ use pest_derive::Parser;
 pub struct AnvilParser;
 
 pub mod ast;
-pub mod parse;
\ No newline at end of file
+pub mod format;
+pub mod import;
+pub mod params;
+pub mod parse;
+pub mod strings;
\ No newline at end of file
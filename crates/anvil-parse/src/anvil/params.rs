@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::NaiveDate;
+
+use anvil_context::resolve;
+
+use crate::anvil::ast::*;
+use crate::diagnostic::{Diagnostic, Diagnostics};
+
+
+/// Values of the declared parameters, None for those whose value was
+/// wrong and has been reported already.
+type Values = HashMap<String, Option<String>>;
+
+/// Give a program's parameters the values in `given`, or otherwise
+/// their defaults, and interpolate them into its strings along with
+/// environment variables. A parameter given but not declared, or
+/// declared without a default and not given, is an error, as is a
+/// string naming a parameter which isn't declared.
+pub fn bind_params(program: &mut Program, given: &[(String, String)]) -> Result<()>
+{
+    let declared = program.params.as_ref().map(|block| block.params.as_slice()).unwrap_or_default();
+    if let Some((name, _)) = given.iter().find(|(name, _)| !declared.iter().any(|p| resolve(p.name) == name)) {
+        bail!("parameter '{name}' isn't declared by the script")
+    }
+
+    let mut errors = vec![];
+    let mut values = Values::new();
+    for param in declared {
+        let name = resolve(param.name);
+        let value = match (given.iter().rfind(|(n, _)| n == name), &param.default) {
+            (Some((_, value)), _) => parse_value(param.ty, value)
+                .ok_or_else(|| anyhow!("parameter '{name}' must be {}, found '{value}'", describe(param.ty))),
+            (None, Some(default)) => default_value(param.ty, default)
+                .ok_or_else(|| anyhow!("default of parameter '{name}' must be {}, found {default}", describe(param.ty))),
+            (None, None) => Err(anyhow!("parameter '{name}' has no default and wasn't given a value")),
+        };
+        if let Err(e) = &value {
+            errors.push(Diagnostic { message: e.to_string(), span: param.span });
+        }
+        values.insert(name.to_string(), value.ok());
+    }
+
+    let flows = program.statements.iter_mut().map(|stmt| (&mut stmt.flow, &mut stmt.branches))
+        .chain(program.defs.iter_mut().map(|def| (&mut def.flow, &mut def.branches)));
+    for (flow, branches) in flows {
+        interpolate_flow(flow, &values, &mut errors);
+        for branch in branches.iter_mut().flatten() {
+            if let Target::Flow { flow, .. } = &mut branch.target {
+                interpolate_flow(flow, &values, &mut errors);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        errors.sort_by_key(|e| e.span.start);
+        Err(Diagnostics(errors).into())
+    }
+}
+
+/// A value given for a parameter in its canonical form, None if it
+/// isn't of the parameter's type.
+fn parse_value(ty: ParamType, value: &str) -> Option<String>
+{
+    let value = match ty {
+        ParamType::String  => value.to_string(),
+        ParamType::Integer => value.parse::<i64>().ok()?.to_string(),
+        ParamType::Float   => value.parse::<f64>().ok().filter(|n| n.is_finite())?.to_string(),
+        ParamType::Boolean => value.parse::<bool>().ok()?.to_string(),
+        ParamType::Date    => NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?.to_string(),
+    };
+
+    Some(value)
+}
+
+fn default_value(ty: ParamType, default: &ArgValue) -> Option<String>
+{
+    let value = match (ty, default) {
+        (ParamType::String, ArgValue::String(s))   => s.clone(),
+        (ParamType::Date, ArgValue::String(s))     => parse_value(ty, s)?,
+        (ParamType::Integer, ArgValue::Integer(n)) => n.to_string(),
+        (ParamType::Float, ArgValue::Integer(n))   => n.to_string(),
+        (ParamType::Float, ArgValue::Float(n))     => n.to_string(),
+        (ParamType::Boolean, ArgValue::Boolean(b)) => b.to_string(),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+/// How values of a type are described in errors.
+fn describe(ty: ParamType) -> &'static str
+{
+    match ty {
+        ParamType::String  => "a string",
+        ParamType::Integer => "an integer",
+        ParamType::Float   => "a number",
+        ParamType::Boolean => "true or false",
+        ParamType::Date    => "a date, YYYY-MM-DD",
+    }
+}
+
+/// Interpolate into the strings of a flow's tool arguments, adding an
+/// error located at the argument for each which can't be.
+fn interpolate_flow(flow: &mut Flow, values: &Values, errors: &mut Vec<Diagnostic>)
+{
+    for item in &mut flow.items {
+        let FlowItem::Tool(tool) = item else { continue };
+        for arg in &mut tool.args {
+            let span = arg.span();
+            if let Err(e) = interpolate_value(arg.value_mut(), values, errors) {
+                errors.push(Diagnostic { message: e.to_string(), span });
+            }
+        }
+    }
+}
+
+fn interpolate_value(value: &mut ArgValue, values: &Values, errors: &mut Vec<Diagnostic>) -> Result<()>
+{
+    match value {
+        ArgValue::Flow(flow) => interpolate_flow(flow, values, errors),
+        ArgValue::String(s) => *s = interpolate(s, values)?,
+        ArgValue::List(items) => {
+            for item in items {
+                interpolate_value(item, values, errors)?;
+            }
+        }
+        ArgValue::Map(entries) => {
+            for (_, value) in entries {
+                interpolate_value(value, values, errors)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// A string with each `${name}` replaced by the value of the parameter
+/// and each `${env:NAME}` by the environment variable. `$${` is written
+/// for a `${` which is kept as it is.
+fn interpolate(s: &str, values: &Values) -> Result<String>
+{
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        if let Some(after) = rest.strip_prefix("$${") {
+            out.push_str("${");
+            rest = after;
+            continue
+        }
+        let Some(after) = rest.strip_prefix("${") else {
+            out.push('$');
+            rest = &rest[1..];
+            continue
+        };
+
+        let end = after.find('}')
+            .ok_or_else(|| anyhow!("'${{' in string isn't closed by '}}', write '$${{' for a '${{' of its own"))?;
+        let name = &after[..end];
+        if let Some(var) = name.strip_prefix("env:") {
+            let value = std::env::var(var).map_err(|_| anyhow!("environment variable '{var}' isn't set"))?;
+            out.push_str(&value);
+        } else {
+            match values.get(name) {
+                Some(Some(value)) => out.push_str(value),
+                Some(None) => out.push_str(&rest[..end + 3]),
+                None => bail!("parameter '{name}' isn't declared"),
+            }
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_program, ASTBuilder};
+
+    fn values(entries: &[(&str, Option<&str>)]) -> Values
+    {
+        entries.iter()
+            .map(|(name, value)| (name.to_string(), value.map(str::to_string)))
+            .collect()
+    }
+
+    /// The first argument of the first tool of a script once its
+    /// parameters are bound.
+    fn bound(script: &str, given: &[(&str, &str)]) -> Result<String>
+    {
+        let given = given.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        let mut program = build_program(&mut ASTBuilder::default(), script)?;
+        bind_params(&mut program, &given)?;
+
+        let FlowItem::Tool(tool) = &program.statements[0].flow.items[0] else { panic!("not a tool") };
+        let ArgValue::String(s) = tool.args[0].value() else { panic!("not a string") };
+        Ok(s.clone())
+    }
+
+    #[test]
+    fn interpolate_params()
+    {
+        let values = values(&[("env", Some("prod")), ("day", Some("2026-10-01"))]);
+        assert_eq!(interpolate("plain", &values).unwrap(), "plain");
+        assert_eq!(interpolate("./${env}/${day}.csv", &values).unwrap(), "./prod/2026-10-01.csv");
+        assert_eq!(interpolate("${env}${env}", &values).unwrap(), "prodprod");
+    }
+
+    #[test]
+    fn interpolate_keeps_dollars()
+    {
+        let values = values(&[("env", Some("prod"))]);
+        assert_eq!(interpolate("$amount > 5", &values).unwrap(), "$amount > 5");
+        assert_eq!(interpolate("$${env} is ${env}", &values).unwrap(), "${env} is prod");
+        assert_eq!(interpolate("ends in $", &values).unwrap(), "ends in $");
+    }
+
+    #[test]
+    fn interpolate_reported_param()
+    {
+        // A parameter whose value was wrong is left as it is
+        let values = values(&[("n", None)]);
+        assert_eq!(interpolate("a ${n} b", &values).unwrap(), "a ${n} b");
+    }
+
+    #[test]
+    fn interpolate_errors()
+    {
+        let values = values(&[]);
+        assert!(interpolate("${missing}", &values).is_err());
+        assert!(interpolate("${unclosed", &values).is_err());
+        assert!(interpolate("${env:ANVIL_TEST_UNSET_VARIABLE}", &values).is_err());
+    }
+
+    #[test]
+    fn bind_defaults_and_given()
+    {
+        let script = "params { env: string = 'dev', n: integer = 5 } [input: '${env}/${n}'];";
+        assert_eq!(bound(script, &[]).unwrap(), "dev/5");
+        assert_eq!(bound(script, &[("env", "prod")]).unwrap(), "prod/5");
+        assert_eq!(bound(script, &[("n", "7"), ("n", "8")]).unwrap(), "dev/8");
+    }
+
+    #[test]
+    fn bind_canonical_values()
+    {
+        let script = "params { f: float, b: boolean, d: date } [input: '${f} ${b} ${d}'];";
+        let given = [("f", "1.50"), ("b", "true"), ("d", "2026-10-01")];
+        assert_eq!(bound(script, &given).unwrap(), "1.5 true 2026-10-01");
+    }
+
+    #[test]
+    fn bind_errors()
+    {
+        let script = "params { n: integer } [input: '${n}'];";
+        assert!(bound(script, &[]).is_err());
+        assert!(bound(script, &[("n", "five")]).is_err());
+        assert!(bound(script, &[("n", "5"), ("m", "6")]).is_err());
+        assert!(bound("params { n: integer = 'five' } [input: 'x'];", &[]).is_err());
+        assert!(bound("[input: '${n}'];", &[]).is_err());
+    }
+}
@@ -1,31 +1,290 @@
 use anyhow::{anyhow, Result};
 use pest::Parser;
-use pest::iterators::Pair;
+use pest::error::InputLocation;
+use pest::iterators::{Pair, Pairs};
 
-use anvil_context::{intern, Symbol};
+use anvil_context::{intern, resolve, Symbol};
 
 use crate::anvil::ast::*;
+use crate::anvil::strings::{unescape, StringScan};
 use crate::anvil::{AnvilParser, Rule};
+use crate::diagnostic::{Diagnostic, Diagnostics};
 
 
+/// Parse a program, reporting every syntax error found rather than
+/// just the first.
 pub fn build_program(builder: &mut ASTBuilder, input: &str) -> Result<Program>
 {
-    let mut pairs = AnvilParser::parse(Rule::PROGRAM, input)?;
-    let program = pairs.next().unwrap();
+    let (program, errors) = recover_program(builder, input);
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(Diagnostics(errors).into())
+    }
+}
 
-    builder.build(program)
+/// Parse as much of a program as possible. A statement with a syntax
+/// error is skipped up to its closing ';' and parsing carries on with
+/// the next one. Returns the statements which parsed along with the
+/// errors, in source order.
+pub fn recover_program(builder: &mut ASTBuilder, input: &str) -> (Program, Vec<Diagnostic>)
+{
+    let mut errors = vec![];
+
+    // Statements with errors are blanked out, keeping the offsets and
+    // lines of the rest, until what's left parses.
+    let mut text = input.to_string();
+    loop {
+        let e = match parse(Rule::PROGRAM, &text) {
+            Ok(mut pairs) => {
+                let mut program = builder.build(pairs.next().unwrap(), &mut errors);
+                program.comments = comments(input);
+                errors.sort_by_key(|e| e.span.start);
+                return (program, errors)
+            }
+            Err(e) => e,
+        };
+
+        let (start, end) = statement_around(&text, e.span.start);
+        errors.push(e);
+        if !blank(&mut text, start, end) {
+            return (Program::default(), errors)
+        }
+    }
 }
 
 pub fn build_statement(builder: &mut ASTBuilder, input: &str) -> Result<Statement>
 {
-    let mut pairs = AnvilParser::parse(Rule::STATEMENT, input)?;
+    let mut pairs = parse(Rule::STATEMENT, input)?;
     let statement = pairs.next().unwrap();
 
     builder.build_statement(statement)
 }
 
+/// The comments in a script, which parsing a program skips.
+fn comments(input: &str) -> Vec<Comment>
+{
+    let Ok(pairs) = AnvilParser::parse(Rule::COMMENTS, input) else { return vec![] };
 
-#[derive(Default)]
+    pairs.flatten()
+        .filter(|pair| pair.as_rule() == Rule::COMMENT_TEXT)
+        .map(|pair| Comment { text: pair.as_str().trim_end().to_string(), span: pair.as_span().into() })
+        .collect()
+}
+
+/// Parse `input` as `rule`, failing with a located syntax error.
+fn parse(rule: Rule, input: &str) -> Result<Pairs<'_, Rule>, Diagnostic>
+{
+    // Track the tokens expected where parsing failed
+    pest::set_error_detail(true);
+
+    AnvilParser::parse(rule, input).map_err(|e| syntax_error(e, input))
+}
+
+/// Syntax error naming the tokens expected where parsing got furthest
+/// and what was found there instead.
+fn syntax_error(e: pest::error::Error<Rule>, input: &str) -> Diagnostic
+{
+    let Some(attempts) = e.parse_attempts() else {
+        let span = match e.location {
+            InputLocation::Pos(pos) => Span { start: pos, end: pos, source: 0 },
+            InputLocation::Span((start, end)) => Span { start, end, source: 0 },
+        };
+        return Diagnostic { message: e.variant.message().to_string(), span }
+    };
+
+    // A string left open runs to the end of the input, so point at
+    // where it starts instead
+    let pos = attempts.max_position;
+    if pos == input.len() && let Some(start) = open_string(input) {
+        return Diagnostic { message: "unterminated string".to_string(), span: Span { start, end: start + 1, source: 0 } }
+    }
+
+    // Name the punctuation expected, or the constructs if a name or
+    // value could also go there. Just after a name, carrying on with
+    // the name isn't worth a mention.
+    let after_name = input[..pos].ends_with(|c: char| c.is_alphanumeric() || c == '_');
+    let tokens = attempts.expected_tokens().iter()
+        .map(|token| token.to_string())
+        .filter(|token| !token.trim().is_empty() && token != "#")
+        .filter(|token| !(after_name && is_name_token(token)))
+        .collect::<Vec<_>>();
+    let mut expected = vec![];
+    if !tokens.iter().any(|token| is_name_token(token)) {
+        // The quotes opening a string are named as one
+        for token in &tokens {
+            let name = if token.starts_with('\'') { "string".to_string() } else { format!("'{token}'") };
+            if !expected.contains(&name) {
+                expected.push(name);
+            }
+        }
+    } else {
+        for stack in attempts.call_stacks() {
+            if let Some(rule) = stack.deepest.get_rule() {
+                let name = describe(rule);
+                if !expected.contains(&name) {
+                    expected.push(name);
+                }
+            }
+        }
+    }
+
+    let found = match input[pos..].chars().next() {
+        None => "end of input".to_string(),
+        Some(c) if c.is_alphanumeric() || c == '_' => {
+            let word = input[pos..].split(|c: char| !(c.is_alphanumeric() || c == '_')).next();
+            format!("'{}'", word.unwrap_or_default())
+        }
+        Some('\'') => "string".to_string(),
+        Some(c) => format!("'{c}'"),
+    };
+
+    // A missing ';' or an unfinished statement is reported at the end
+    // of the line, not at the start of the next one.
+    let end = input[..pos].trim_end().len();
+    let pos = if input[end..pos].contains('\n') && (pos == input.len() || expected.iter().any(|t| t == "';'")) {
+        end
+    } else {
+        pos
+    };
+
+    let message = match expected.split_last() {
+        None => format!("unexpected {found}"),
+        Some((last, [])) => format!("expected {last}, found {found}"),
+        Some((last, rest)) => format!("expected {} or {last}, found {found}", rest.join(", ")),
+    };
+
+    Diagnostic { message, span: Span { start: pos, end: pos, source: 0 } }
+}
+
+/// Whether an expected token starts or continues a name or number,
+/// e.g. a character range 'a..z', rather than being punctuation.
+fn is_name_token(token: &str) -> bool
+{
+    token == "_" || token.chars().any(|c| c.is_alphanumeric())
+}
+
+/// How a rule is named in syntax errors.
+fn describe(rule: &Rule) -> String
+{
+    let name = match rule {
+        Rule::BIND | Rule::OUTPUT_BINDING => "'>'",
+        Rule::BRANCH_BLOCK => "':'",
+        Rule::PIPE         => "'|'",
+        Rule::EOI          => "end of input",
+        Rule::PARAMS       => "parameters",
+        Rule::PARAM        => "parameter",
+        Rule::PARAM_TYPE   => "type",
+        Rule::IMPORT       => "import",
+        Rule::DEF          => "definition",
+        Rule::DEF_PARAM    => "parameter",
+        Rule::DEF_OUTPUTS  => "outputs",
+        Rule::STATEMENT    => "statement",
+        Rule::FLOW         => "flow",
+        Rule::BRANCHES | Rule::BRANCH => "branch",
+        Rule::TARGET       => "flow or variable",
+        Rule::TOOL_REF     => "tool",
+        Rule::TOOL_ARGS    => "arguments",
+        Rule::KEYWORD      => "named argument",
+        Rule::POSITIONAL   => "argument",
+        Rule::VALUE        => "value",
+        Rule::LITERAL      => "literal",
+        Rule::STRING       => "string",
+        Rule::NUMBER       => "number",
+        Rule::BOOLEAN      => "boolean",
+        Rule::NULL         => "null",
+        Rule::LIST         => "list",
+        Rule::MAP          => "map",
+        Rule::ENTRY        => "map entry",
+        Rule::IDENTIFIER   => "identifier",
+        Rule::VARIABLE     => "variable",
+        _ => return format!("{rule:?}"),
+    };
+
+    name.to_string()
+}
+
+/// Start and end of the statement containing `pos`, from just after
+/// the previous ';' through the next, skipping any in strings and
+/// comments.
+fn statement_around(text: &str, pos: usize) -> (usize, usize)
+{
+    let mut start = 0;
+    let mut strings = StringScan::default();
+    let mut in_comment = false;
+
+    for (i, c) in text.char_indices() {
+        if in_comment {
+            in_comment = c != '\n';
+            continue
+        }
+        if strings.step(&text[i..]) {
+            continue
+        }
+        match c {
+            '#'  => in_comment = true,
+            ';' if i < pos => start = i + 1,
+            ';'  => return (start, i + 1),
+            _ => {}
+        }
+    }
+
+    (start, text.len())
+}
+
+/// Start of a string which isn't closed by the end of `text`.
+fn open_string(text: &str) -> Option<usize>
+{
+    let mut start = None;
+    let mut strings = StringScan::default();
+    let mut in_comment = false;
+
+    for (i, c) in text.char_indices() {
+        if in_comment {
+            in_comment = c != '\n';
+        } else if strings.step(&text[i..]) {
+            start = start.or(Some(i));
+        } else {
+            start = None;
+            in_comment = c == '#';
+        }
+    }
+
+    start.filter(|_| strings.in_string())
+}
+
+/// A variable being bound. Only imports qualify names, so a name with
+/// a '.' can't be bound by a statement.
+fn binding(var: Pair<Rule>) -> Result<(Symbol, Span)>
+{
+    let span = var.as_span().into();
+    if var.as_str().contains('.') {
+        let message = format!("can't bind '{}', only imported variables are qualified by a name", var.as_str());
+        return Err(Diagnostic { message, span }.into())
+    }
+
+    Ok((intern(var.as_str()), span))
+}
+
+/// Replace the text between `start` and `end` with spaces, keeping
+/// line breaks. Returns false if there was nothing to blank.
+fn blank(text: &mut String, start: usize, end: usize) -> bool
+{
+    let blanked = text[start..end].chars()
+        .map(|c| match c {
+            ' ' | '\t' | '\r' | '\n' => c.to_string(),
+            _ => " ".repeat(c.len_utf8()),
+        })
+        .collect::<String>();
+
+    let changed = blanked != text[start..end];
+    text.replace_range(start..end, &blanked);
+
+    changed
+}
+
+
+#[derive(Clone, Default)]
 pub struct ASTBuilder {
     next_tool_id: usize,
 }
@@ -36,7 +295,9 @@ impl ASTBuilder {
         Self { next_tool_id: 1 }
     }
 
-    fn get_next_id(&mut self) -> ToolId
+    /// A tool id not given to any other tool, e.g. for a tool of a
+    /// definition where it's used.
+    pub fn next_id(&mut self) -> ToolId
     {
         let id = self.next_tool_id;
         self.next_tool_id += 1;
@@ -44,21 +305,122 @@ impl ASTBuilder {
         ToolId(id)
     }
 
-    fn build(&mut self, program: Pair<Rule>) -> Result<Program>
+    /// Build the program's statements, adding an error for each which
+    /// can't be built.
+    fn build(&mut self, program: Pair<Rule>, errors: &mut Vec<Diagnostic>) -> Program
     {
+        let mut params = None;
+        let mut imports = Vec::new();
         let mut statements = Vec::new();
+        let mut defs = Vec::new();
 
         for pair in program.into_inner() {
-            if pair.as_rule() == Rule::STATEMENT {
-                statements.push(self.build_statement(pair)?);
+            let span = pair.as_span().into();
+            let built = match pair.as_rule() {
+                Rule::PARAMS => self.build_params(pair).map(|block| params = Some(block)),
+                Rule::IMPORT => self.build_import(pair).map(|import| imports.push(import)),
+                Rule::STATEMENT => self.build_statement(pair).map(|stmt| statements.push(stmt)),
+                Rule::DEF => self.build_def(pair).map(|def| defs.push(def)),
+                _ => Ok(()),
+            };
+            if let Err(e) = built {
+                errors.push(e.downcast().unwrap_or_else(|e| {
+                    Diagnostic { message: format!("{e:#}"), span }
+                }));
             }
         }
 
-        Ok(Program { statements })
+        Program { params, imports, statements, defs, comments: vec![] }
+    }
+
+    fn build_params(&self, pair: Pair<Rule>) -> Result<ParamBlock>
+    {
+        let span = pair.as_span().into();
+        let mut params: Vec<Param> = vec![];
+
+        for param in pair.into_inner() {
+            let span = param.as_span().into();
+            let mut inner = param.into_inner();
+            let name = inner.next().unwrap().as_str();
+            if params.iter().any(|p| resolve(p.name) == name) {
+                return Err(Diagnostic { message: format!("parameter '{name}' is declared twice"), span }.into())
+            }
+
+            let ty = ParamType::from_name(inner.next().unwrap().as_str())
+                .ok_or_else(|| anyhow!("invalid parameter type"))?;
+            let default = inner.next()
+                .map(|value| self.build_literal(value))
+                .transpose()?;
+            params.push(Param { name: intern(name), ty, default, span });
+        }
+
+        Ok(ParamBlock { params, span })
+    }
+
+    fn build_import(&self, pair: Pair<Rule>) -> Result<Import>
+    {
+        let span = pair.as_span().into();
+        let mut inner = pair.into_inner();
+        let path = self.build_string(inner.next().unwrap())?;
+        let alias = inner.next().map(|alias| (intern(alias.as_str()), alias.as_span().into()));
+
+        Ok(Import { path, alias, span })
+    }
+
+    fn build_def(&mut self, pair: Pair<Rule>) -> Result<Def>
+    {
+        let span = pair.as_span().into();
+        let mut inner = pair.into_inner();
+        let name = intern(inner.next().unwrap().as_str());
+
+        let mut params: Vec<(Symbol, Option<ArgValue>)> = vec![];
+        let mut outputs = vec![];
+        let mut flow = None;
+        let mut branches = None;
+        for pair in inner {
+            match pair.as_rule() {
+                Rule::DEF_PARAM => {
+                    let span = pair.as_span().into();
+                    let mut inner = pair.into_inner();
+                    let param = intern(inner.next().unwrap().as_str());
+                    if params.iter().any(|(p, _)| *p == param) {
+                        let message = format!("parameter '{}' is declared twice", resolve(param));
+                        return Err(Diagnostic { message, span }.into())
+                    }
+                    let default = inner.next()
+                        .map(|value| self.build_literal(value))
+                        .transpose()?;
+                    params.push((param, default));
+                }
+                Rule::DEF_OUTPUTS => {
+                    for output in pair.into_inner() {
+                        let name = intern(output.as_str());
+                        if outputs.contains(&name) {
+                            let message = format!("output '{}' is declared twice", output.as_str());
+                            return Err(Diagnostic { message, span: output.as_span().into() }.into())
+                        }
+                        outputs.push(name);
+                    }
+                }
+                Rule::FLOW => flow = Some(self.build_flow(pair)?),
+                Rule::BRANCH_BLOCK => branches = Some(self.build_branches(pair)?),
+                _ => {}
+            }
+        }
+
+        Ok(Def {
+            name,
+            params,
+            outputs,
+            flow: flow.ok_or_else(|| anyhow!("definition missing flow"))?,
+            branches,
+            span,
+        })
     }
 
     fn build_statement(&mut self, pair: Pair<Rule>) -> Result<Statement>
     {
+        let span = pair.as_span().into();
         let mut flow = None;
         let mut branches = None;
         let mut variable = None;
@@ -82,6 +444,7 @@ impl ASTBuilder {
             flow: flow.ok_or_else(|| anyhow!("statement missing flow"))?,
             branches,
             variable,
+            span,
         })
     }
 
@@ -104,6 +467,7 @@ impl ASTBuilder {
 
     fn build_branch(&mut self, pair: Pair<Rule>) -> Result<Branch>
     {
+        let span = pair.as_span().into();
         let mut inner = pair.into_inner();
         let name   = inner.next().unwrap();
         let target = inner.next().unwrap();
@@ -112,38 +476,42 @@ impl ASTBuilder {
         Ok(Branch {
             name: intern(name.as_str()),
             target,
+            span,
         })
     }
 
     fn build_target(&mut self, pair: Pair<Rule>) -> Result<Target>
     {
-        let inner = pair.into_inner().next()
+        let mut inner = pair.into_inner();
+        let target = inner.next()
             .ok_or_else(|| anyhow!("branch target must have one child"))?;
 
-        match inner.as_rule() {
-            Rule::VARIABLE => {
-                Ok(Target::Variable(intern(inner.as_str())))
-            }
+        match target.as_rule() {
+            Rule::VARIABLE => binding(target).map(|(name, span)| Target::Variable(name, span)),
             Rule::FLOW => {
-                let flow = self.build_flow(inner)?;
-                Ok(Target::Flow { flow, variable: None })
+                let flow = self.build_flow(target)?;
+                let variable = inner.next()
+                    .map(|binding| self.build_variable_binding(binding))
+                    .transpose()?;
+                Ok(Target::Flow { flow, variable })
             }
             _ => Err(anyhow!("invalid branch target")),
         }
     }
 
-    fn build_variable_binding(&self, pair: Pair<Rule>) -> Result<Symbol>
+    fn build_variable_binding(&self, pair: Pair<Rule>) -> Result<(Symbol, Span)>
     {
         let var = pair
             .into_inner()
             .find(|p| p.as_rule() == Rule::VARIABLE)
             .ok_or_else(|| anyhow!("output binding missing variable"))?;
 
-        Ok(intern(var.as_str()))
+        binding(var)
     }
 
     fn build_flow(&mut self, flow: Pair<Rule>) -> Result<Flow>
     {
+        let span = flow.as_span().into();
         let mut items = vec![];
 
         for flow_item in flow.into_inner() {
@@ -153,40 +521,42 @@ impl ASTBuilder {
                     items.push(FlowItem::Tool(self.build_tool_ref(flow_item)?))
                 }
                 Rule::VARIABLE => {
-                    items.push(FlowItem::Variable(intern(flow_item.as_str())))
+                    items.push(FlowItem::Variable(intern(flow_item.as_str()), flow_item.as_span().into()))
                 }
                 _ => return Err(anyhow!("invalid flow item: {:?}", flow_item.as_rule()))
             }
         }
 
-        Ok(Flow { items })
+        Ok(Flow { items, span })
     }
 
     fn build_tool_ref(&mut self, pair: Pair<Rule>) -> Result<ToolRef>
     {
+        let span = pair.as_span().into();
         let mut inner = pair.into_inner();
         let name = intern(inner.next().unwrap().as_str());
 
         let mut args = vec![];
         if let Some(tool_args) = inner.next() {
             for arg in tool_args.into_inner() {
+                let span = arg.as_span().into();
                 match arg.as_rule() {
                     Rule::POSITIONAL => {
                         let value = arg.into_inner().next().unwrap();
-                        args.push(ToolArg::Positional(self.build_arg_value(value)?))
+                        args.push(ToolArg::Positional(self.build_arg_value(value)?, span))
                     }
                     Rule::KEYWORD => {
                         let mut inner = arg.into_inner();
                         let ident = intern(inner.next().unwrap().as_str());
                         let value = self.build_arg_value(inner.next().unwrap())?;
-                        args.push(ToolArg::Keyword { ident, value })
+                        args.push(ToolArg::Keyword { ident, value, span })
                     }
                     _ => return Err(anyhow!("unexpected tool argument {:?}", arg.as_rule()))
                 }
             }
         }
 
-        Ok(ToolRef { id: self.get_next_id(), name, args })
+        Ok(ToolRef { id: self.next_id(), name, args, span })
     }
 
     fn build_arg_value(&mut self, pair: Pair<Rule>) -> Result<ArgValue>
@@ -197,7 +567,7 @@ impl ASTBuilder {
         let v = match inner.as_rule() {
             Rule::FLOW       => ArgValue::Flow(self.build_flow(inner)?),
             Rule::LITERAL    => self.build_literal(inner)?,
-            Rule::IDENTIFIER => ArgValue::Ident(inner.as_str().to_string()),
+            Rule::VARIABLE   => ArgValue::Ident(inner.as_str().to_string(), inner.as_span().into()),
             _ => return Err(anyhow!("unexpected arg value {:?}", inner.as_rule()))
         };
 
@@ -210,15 +580,134 @@ impl ASTBuilder {
 
         let av = match inner.as_rule() {
             Rule::BOOLEAN => ArgValue::Boolean(inner.as_str() == "true"),
-            Rule::NUMBER  => ArgValue::Integer(inner.as_str().parse::<i64>()?),
-            Rule::STRING  => {
-                let s = inner.as_str();
-                let v = &s[1..s.len() - 1];
-                ArgValue::String(v.to_string())
+            Rule::NUMBER  => self.build_number(inner)?,
+            Rule::STRING  => ArgValue::String(self.build_string(inner)?),
+            Rule::NULL    => ArgValue::Null,
+            Rule::LIST    => {
+                let values = inner.into_inner()
+                    .map(|value| self.build_literal(value))
+                    .collect::<Result<_>>()?;
+                ArgValue::List(values)
+            }
+            Rule::MAP     => {
+                let mut entries: Vec<(String, ArgValue)> = vec![];
+                for entry in inner.into_inner() {
+                    let span = entry.as_span().into();
+                    let mut inner = entry.into_inner();
+                    let key = inner.next().unwrap();
+                    let key = match key.as_rule() {
+                        Rule::STRING => self.build_string(key)?,
+                        _ => key.as_str().to_string(),
+                    };
+                    if entries.iter().any(|(k, _)| *k == key) {
+                        return Err(Diagnostic { message: format!("duplicate map key '{key}'"), span }.into())
+                    }
+                    entries.push((key, self.build_literal(inner.next().unwrap())?));
+                }
+                ArgValue::Map(entries)
             }
             _ => return Err(anyhow!("unexpected literal {:?}", inner.as_rule()))
         };
 
         Ok(av)
     }
+
+    /// Integers unless written with a fraction or exponent.
+    fn build_number(&self, pair: Pair<Rule>) -> Result<ArgValue>
+    {
+        let s = pair.as_str();
+        let span = pair.as_span().into();
+        let out_of_range = || Diagnostic { message: format!("number out of range: {s}"), span };
+
+        if s.contains(['.', 'e', 'E']) {
+            let n = s.parse::<f64>().ok().filter(|n| n.is_finite()).ok_or_else(out_of_range)?;
+            Ok(ArgValue::Float(n))
+        } else {
+            Ok(ArgValue::Integer(s.parse::<i64>().map_err(|_| out_of_range())?))
+        }
+    }
+
+    /// Value of a string, raw or with its escapes replaced.
+    fn build_string(&self, pair: Pair<Rule>) -> Result<String>
+    {
+        let s = pair.as_str();
+        if let Some(raw) = s.strip_prefix("'''") {
+            return Ok(raw[..raw.len() - 3].to_string())
+        }
+
+        unescape(&s[1..s.len() - 1])
+            .map_err(|e| Diagnostic { message: e.to_string(), span: pair.as_span().into() }.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recover(input: &str) -> (Vec<String>, Vec<(String, usize)>)
+    {
+        let (program, errors) = recover_program(&mut ASTBuilder::new(), input);
+        let statements = program.statements.iter().map(|stmt| stmt.to_string()).collect();
+        let errors = errors.into_iter().map(|e| (e.message, e.span.start)).collect();
+        (statements, errors)
+    }
+
+    #[test]
+    fn recover_nothing_wrong()
+    {
+        let (statements, errors) = recover("a | [print];\nb | [print];\n");
+        assert_eq!(statements.len(), 2);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn recover_skips_broken_statement()
+    {
+        let input = "a | [print];\nb | | [print];\nc | [print];\n";
+        let (statements, errors) = recover(input);
+        assert_eq!(statements.len(), 2);
+        assert!(statements[0].starts_with('a'), "{statements:?}");
+        assert!(statements[1].starts_with('c'), "{statements:?}");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].1, input.find("| [print];\nc").unwrap());
+    }
+
+    #[test]
+    fn recover_errors_in_order()
+    {
+        let input = "a | | [print];\nb | [print];\nc | [print] d;\n";
+        let (statements, errors) = recover(input);
+        assert_eq!(statements.len(), 1);
+        assert!(statements[0].starts_with('b'), "{statements:?}");
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].1 < input.find('b').unwrap());
+        assert!(errors[1].1 > input.find('c').unwrap());
+    }
+
+    #[test]
+    fn recover_semicolon_in_string()
+    {
+        let input = "[sql: 'SELECT 1; SELECT 2'] | | x;\nb | [print];\n";
+        let (statements, errors) = recover(input);
+        assert_eq!(statements.len(), 1);
+        assert!(statements[0].starts_with('b'), "{statements:?}");
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn recover_unterminated_string()
+    {
+        let input = "a | [print];\n[input: 'x.csv] | [print];\n";
+        let (_, errors) = recover(input);
+        assert_eq!(errors, [("unterminated string".to_string(), input.find('\'').unwrap())]);
+    }
+
+    #[test]
+    fn recover_keeps_comments()
+    {
+        let (program, errors) = recover_program(&mut ASTBuilder::new(), "# note\na | | [print];\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(program.comments.len(), 1);
+        assert_eq!(program.comments[0].text, "# note");
+    }
 }
@@ -0,0 +1,191 @@
+use anyhow::{anyhow, Result};
+
+
+/// The value of a quoted string's contents with its escapes replaced:
+/// `\'`, `\n`, `\t` and `\\`.
+pub fn unescape(s: &str) -> Result<String>
+{
+    let mut value = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue
+        }
+        match chars.next() {
+            Some('\'') => value.push('\''),
+            Some('n')  => value.push('\n'),
+            Some('t')  => value.push('\t'),
+            Some('\\') => value.push('\\'),
+            Some(c) => return Err(anyhow!("unknown escape '\\{c}' in string, use '\\\\' for a backslash")),
+            None    => return Err(anyhow!("string ends with an unfinished escape")),
+        }
+    }
+
+    Ok(value)
+}
+
+/// A string as a literal. Strings spanning lines are written raw,
+/// when they can be, and others quoted with escapes.
+pub fn quote(s: &str) -> String
+{
+    if s.contains('\n') && !s.contains("'''") && !s.ends_with('\'') {
+        return format!("'''{s}'''")
+    }
+
+    let mut literal = String::with_capacity(s.len() + 2);
+    literal.push('\'');
+    for c in s.chars() {
+        match c {
+            '\'' => literal.push_str("\\'"),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            c => literal.push(c),
+        }
+    }
+    literal.push('\'');
+
+    literal
+}
+
+/// Follows string literals through source scanned a character at a
+/// time, for scanners which look at scripts without parsing them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StringScan {
+    state: State,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum State {
+    #[default]
+    Outside,
+    Quoted,
+    /// Just after a '\' in a quoted string
+    Escaped,
+    /// Within the quotes opening or closing a raw string, how many
+    /// are left
+    Opening(usize),
+    Raw,
+    Closing(usize),
+}
+
+impl StringScan {
+    /// Whether the character starting `rest` is part of a string,
+    /// its quotes included, moving past it.
+    pub fn step(&mut self, rest: &str) -> bool
+    {
+        self.state = match self.state {
+            State::Outside if rest.starts_with("'''") => State::Opening(2),
+            State::Outside if rest.starts_with('\'')  => State::Quoted,
+            State::Outside => return false,
+            State::Quoted if rest.starts_with('\\') => State::Escaped,
+            State::Quoted if rest.starts_with('\'') => State::Outside,
+            State::Quoted | State::Escaped => State::Quoted,
+            State::Opening(1) => State::Raw,
+            State::Opening(n) => State::Opening(n - 1),
+            State::Raw if rest.starts_with("'''") => State::Closing(2),
+            State::Raw => State::Raw,
+            State::Closing(1) => State::Outside,
+            State::Closing(n) => State::Closing(n - 1),
+        };
+
+        true
+    }
+
+    /// Whether the scan is within a string which hasn't been closed.
+    pub fn in_string(&self) -> bool
+    {
+        self.state != State::Outside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `src` with each character in a string replaced by 's'.
+    fn scan(src: &str) -> String
+    {
+        let mut strings = StringScan::default();
+        src.char_indices()
+            .map(|(i, c)| if strings.step(&src[i..]) { 's' } else { c })
+            .collect()
+    }
+
+    /// Whether `src` ends within a string.
+    fn in_string(src: &str) -> bool
+    {
+        let mut strings = StringScan::default();
+        for (i, _) in src.char_indices() {
+            strings.step(&src[i..]);
+        }
+        strings.in_string()
+    }
+
+    #[test]
+    fn unescape_escapes()
+    {
+        assert_eq!(unescape("plain").unwrap(), "plain");
+        assert_eq!(unescape(r"it\'s").unwrap(), "it's");
+        assert_eq!(unescape(r"a\nb\tc").unwrap(), "a\nb\tc");
+        assert_eq!(unescape(r"C:\\dir").unwrap(), r"C:\dir");
+    }
+
+    #[test]
+    fn unescape_rejects_bad_escapes()
+    {
+        assert!(unescape(r"\d").is_err());
+        assert!(unescape(r"end\").is_err());
+    }
+
+    #[test]
+    fn quote_escapes()
+    {
+        assert_eq!(quote("plain"), "'plain'");
+        assert_eq!(quote("it's"), r"'it\'s'");
+        assert_eq!(quote(r"C:\dir"), r"'C:\\dir'");
+        assert_eq!(quote("a\tb"), r"'a\tb'");
+    }
+
+    #[test]
+    fn quote_writes_lines_raw()
+    {
+        assert_eq!(quote("a\nb"), "'''a\nb'''");
+        assert_eq!(quote("a\n'''b"), r"'a\n\'\'\'b'");
+        assert_eq!(quote("a\nb'"), r"'a\nb\''");
+    }
+
+    #[test]
+    fn quote_round_trips()
+    {
+        for s in ["", "plain", "it's", r"C:\dir", "a\tb", "a\n'''b", "a\nb'", "\\'"] {
+            let literal = quote(s);
+            assert_eq!(unescape(&literal[1..literal.len() - 1]).unwrap(), s, "{literal}");
+        }
+    }
+
+    #[test]
+    fn scan_quoted()
+    {
+        assert_eq!(scan("[a: 'x y'] | b"), "[a: sssss] | b");
+        assert_eq!(scan(r"'it\'s' x"), "sssssss x");
+        assert_eq!(scan(r"'a\\' x"), "sssss x");
+    }
+
+    #[test]
+    fn scan_raw()
+    {
+        assert_eq!(scan("'''a\n'b''' x"), "ssssssssss x");
+        assert_eq!(scan("x '''a''' y"), "x sssssss y");
+    }
+
+    #[test]
+    fn scan_unclosed()
+    {
+        assert!(in_string("[a: 'b"));
+        assert!(in_string("[a: '''b\n''"));
+        assert!(!in_string("'a' '''b'''"));
+    }
+}
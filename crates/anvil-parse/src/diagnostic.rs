@@ -0,0 +1,199 @@
+use std::fmt;
+
+use anyhow::Result;
+
+/// Byte range of a construct in the source it was parsed from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    /// The script being built when 0, otherwise the id of an imported
+    /// script in the program's `Sources`.
+    pub source: usize,
+}
+
+impl From<pest::Span<'_>> for Span {
+    fn from(span: pest::Span<'_>) -> Span
+    {
+        Span { start: span.start(), end: span.end(), source: 0 }
+    }
+}
+
+/// An error at a location in the source.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+impl Diagnostic {
+    /// The message followed by the file name, line and column and the
+    /// source line with the span underlined.
+    pub fn render(&self, name: &str, source: &str, sources: &Sources) -> String
+    {
+        let (name, source) = match self.span.source {
+            0 => (name, source),
+            id => match sources.get(id) {
+                Some((name, source)) => (name.as_str(), source.as_str()),
+                None => return format!("error: {}", self.message),
+            },
+        };
+
+        // Spans from another source, e.g. an earlier REPL statement,
+        // are reported without a snippet.
+        let Some(before) = source.get(..self.span.start) else {
+            return format!("error: {}", self.message)
+        };
+
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_no = before.matches('\n').count() + 1;
+        let line = source[line_start..].lines().next().unwrap_or_default();
+        let prefix = &source[line_start..self.span.start];
+        let column = prefix.chars().count() + 1;
+
+        // Underline up to the end of the span's first line, keeping
+        // tabs so the carets line up with the snippet.
+        let end = self.span.end.clamp(self.span.start, line_start + line.len());
+        let width = source.get(self.span.start..end).map(|s| s.chars().count()).unwrap_or(0).max(1);
+        let indent = prefix.chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect::<String>();
+
+        let gutter = " ".repeat(line_no.to_string().len());
+        format!(
+            "error: {}\n{gutter}--> {name}:{line_no}:{column}\n{gutter} |\n{line_no} | {line}\n{gutter} | {indent}{}",
+            self.message, "^".repeat(width)
+        )
+    }
+}
+
+/// Scripts imported while building programs, which spans parsed from
+/// them refer to by id so errors in them render against them.
+#[derive(Debug, Default)]
+pub struct Sources {
+    sources: Vec<(String, String)>,
+}
+
+impl Sources {
+    /// Add a script, returning its id. A script added again unchanged,
+    /// e.g. one imported on each run at the REPL, keeps its id rather
+    /// than being added twice.
+    pub fn add(&mut self, name: &str, text: &str) -> usize
+    {
+        if let Some(i) = self.sources.iter().position(|(n, t)| n == name && t == text) {
+            return i + 1
+        }
+        self.sources.push((name.to_string(), text.to_string()));
+
+        self.sources.len()
+    }
+
+    /// Name and text of the script with an id.
+    fn get(&self, id: usize) -> Option<&(String, String)>
+    {
+        self.sources.get(id.checked_sub(1)?)
+    }
+}
+
+/// Errors reported together, e.g. every problem found in a plan.
+#[derive(Debug)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        let messages = self.0.iter().map(|d| d.message.as_str()).collect::<Vec<_>>();
+        write!(f, "{}", messages.join("\n"))
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// Attach a location to errors which don't have one yet.
+pub trait Locate<T> {
+    fn at(self, span: Span) -> Result<T>;
+}
+
+impl<T> Locate<T> for Result<T> {
+    fn at(self, span: Span) -> Result<T>
+    {
+        self.map_err(|e| locate(e, span))
+    }
+}
+
+/// The error located at `span`, errors already located are kept as is.
+pub fn locate(error: anyhow::Error, span: Span) -> anyhow::Error
+{
+    if error.is::<Diagnostic>() || error.is::<Diagnostics>() {
+        error
+    } else {
+        Diagnostic { message: format!("{error:#}"), span }.into()
+    }
+}
+
+/// Render an error for display, with the file name, line, column and
+/// an underlined snippet of `source`, or the script it imported, for
+/// each located error.
+pub fn render(error: &anyhow::Error, name: &str, source: &str, sources: &Sources) -> String
+{
+    if let Some(d) = error.downcast_ref::<Diagnostic>() {
+        d.render(name, source, sources)
+    } else if let Some(ds) = error.downcast_ref::<Diagnostics>() {
+        let rendered = ds.0.iter().map(|d| d.render(name, source, sources)).collect::<Vec<_>>();
+        rendered.join("\n")
+    } else {
+        format!("error: {error:#}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(start: usize, end: usize, source: usize) -> Diagnostic
+    {
+        Diagnostic { message: "bad".to_string(), span: Span { start, end, source } }
+    }
+
+    #[test]
+    fn render_in_script()
+    {
+        let rendered = error(4, 7, 0).render("main.anvil", "a;\nb | c;\n", &Sources::default());
+        assert_eq!(rendered, "error: bad\n --> main.anvil:2:2\n  |\n2 | b | c;\n  |  ^^^");
+    }
+
+    #[test]
+    fn render_in_import()
+    {
+        let mut sources = Sources::default();
+        let id = sources.add("lib.anvil", "x | y;\n");
+        let rendered = error(4, 5, id).render("main.anvil", "a;\n", &sources);
+        assert_eq!(rendered, "error: bad\n --> lib.anvil:1:5\n  |\n1 | x | y;\n  |     ^");
+    }
+
+    #[test]
+    fn render_unknown_source()
+    {
+        assert_eq!(error(0, 1, 3).render("main.anvil", "a;\n", &Sources::default()), "error: bad");
+        assert_eq!(error(10, 11, 0).render("main.anvil", "a;\n", &Sources::default()), "error: bad");
+    }
+
+    #[test]
+    fn sources_added_once()
+    {
+        let mut sources = Sources::default();
+        let a = sources.add("a.anvil", "a;");
+        let b = sources.add("b.anvil", "b;");
+        assert_ne!(a, 0);
+        assert_ne!(a, b);
+        assert_eq!(sources.add("a.anvil", "a;"), a);
+        assert_ne!(sources.add("a.anvil", "changed;"), a);
+    }
+}
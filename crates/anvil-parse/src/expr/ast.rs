@@ -23,6 +23,14 @@ pub enum Expr {
         name: String,
         args: Vec<Expr>,
     },
+
+    Wildcard,
+}
+
+#[derive(Debug, Clone)]
+pub struct SortKey {
+    pub expr: Expr,
+    pub ascending: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
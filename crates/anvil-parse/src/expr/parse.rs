@@ -15,6 +15,28 @@ pub fn parse_expression(input: &str) -> Result<Expr>
     parse_expr(expr)
 }
 
+pub fn parse_expressions(input: &str) -> Result<Vec<Expr>>
+{
+    let mut pairs = ExprParser::parse(Rule::expression_list, input)?;
+    let list = pairs.next().unwrap();
+
+    list.into_inner()
+        .filter(|p| p.as_rule() == Rule::logical)
+        .map(parse_logical)
+        .collect()
+}
+
+pub fn parse_sort_keys(input: &str) -> Result<Vec<SortKey>>
+{
+    let mut pairs = ExprParser::parse(Rule::sort_list, input)?;
+    let list = pairs.next().unwrap();
+
+    list.into_inner()
+        .filter(|p| p.as_rule() == Rule::sort_key)
+        .map(parse_sort_key)
+        .collect()
+}
+
 pub fn parse_expr(pair: Pair<Rule>) -> Result<Expr>
 {
     let inner = pair.into_inner().next()
@@ -23,6 +45,20 @@ pub fn parse_expr(pair: Pair<Rule>) -> Result<Expr>
     parse_assignment(inner)
 }
 
+fn parse_sort_key(pair: Pair<Rule>) -> Result<SortKey>
+{
+    let mut inner = pair.into_inner();
+    let x = inner.next()
+        .ok_or_else(|| anyhow!("empty sort key"))?;
+
+    let expr = parse_logical(x)?;
+    let ascending = inner.next()
+        .map(|dir| !dir.as_str().eq_ignore_ascii_case("desc"))
+        .unwrap_or(true);
+
+    Ok(SortKey { expr, ascending })
+}
+
 fn parse_assignment(pair: Pair<Rule>) -> Result<Expr>
 {
     let mut inner = pair.into_inner();
@@ -230,7 +266,12 @@ fn parse_call(pair: Pair<Rule>) -> Result<Expr>
         .ok_or_else(|| anyhow!("empty function"))?;
     let name = x.as_str().to_string();
 
-    let args = inner.map(parse_logical).collect::<Result<Vec<_>, _>>()?;
+    let args = inner
+        .map(|p| match p.as_rule() {
+            Rule::wildcard => Ok(Expr::Wildcard),
+            _ => parse_logical(p),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
     Ok(Expr::Call { name, args })
 }
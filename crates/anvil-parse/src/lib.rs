@@ -1,5 +1,11 @@
 pub mod anvil;
+pub mod diagnostic;
 pub mod expr;
 
-pub use anvil::parse::{ASTBuilder, build_program, build_statement};
-pub use expr::parse::parse_expression;
\ No newline at end of file
+pub use anvil::format::format_script;
+pub use anvil::import::load_imports;
+pub use anvil::params::bind_params;
+pub use anvil::parse::{ASTBuilder, build_program, build_statement, recover_program};
+pub use anvil::strings::StringScan;
+pub use diagnostic::{locate, render, Diagnostic, Diagnostics, Locate, Sources, Span};
+pub use expr::parse::{parse_expression, parse_expressions, parse_sort_keys};
@@ -0,0 +1,375 @@
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use anvil_context::{intern, resolve, tool_types};
+use anvil_parse::{ASTBuilder, StringScan};
+use crate::tools::spec;
+use crate::{ExecNode, Executor, Planner};
+
+/// Variable the input of the tool being edited is bound to, to plan
+/// it for its columns.
+const CURSOR: &str = "__cursor";
+
+/// Completes tool names, keyword arguments, variables and, inside
+/// expression strings, `$column` names of the flow being edited.
+#[derive(Default)]
+pub struct ReplHelper {
+    commands: &'static [&'static str],
+    /// Variables bound in the session.
+    vars: Vec<String>,
+    /// The session as of the prompt, which the statement being edited
+    /// is planned on a copy of.
+    builder: ASTBuilder,
+    planner: Planner,
+    executor: Executor,
+    /// Earlier lines of a statement spanning several lines.
+    pending: String,
+}
+
+impl ReplHelper {
+    pub fn new(commands: &'static [&'static str]) -> ReplHelper
+    {
+        ReplHelper { commands, ..Default::default() }
+    }
+
+    /// Refresh the variables and the session after each run.
+    pub fn update(&mut self, builder: &ASTBuilder, planner: &Planner, executor: &Executor)
+    {
+        self.vars = planner.vars().keys().map(|name| resolve(*name).to_string()).collect();
+        self.builder = builder.clone();
+        self.planner = planner.clone();
+        self.executor = executor.clone();
+    }
+
+    pub fn set_pending(&mut self, pending: &str)
+    {
+        self.pending = pending.to_string();
+    }
+
+    fn candidates(&self, before: &str, word: &str) -> Vec<String>
+    {
+        let text = if self.pending.is_empty() {
+            before.to_string()
+        } else {
+            format!("{}\n{before}", self.pending)
+        };
+        if let Some(candidates) = completions(&text, word, &self.vars, |source| self.columns(source)) {
+            return candidates
+        }
+
+        let vars = self.vars.iter().cloned();
+        let preceding = &text[..text.len() - word.len()];
+        match preceding.trim() {
+            "" => self.commands.iter().map(|c| c.to_string()).chain(vars).collect(),
+            "show" | "drop" => vars.collect(),
+            "help" => tool_types().keys().map(|s| resolve(*s).to_string()).collect(),
+            _ => vars.collect(),
+        }
+    }
+
+    /// Columns of the data `source` binds to `CURSOR`, planned on a copy
+    /// of the session and inferred without running anything.
+    fn columns(&self, source: &str) -> Vec<String>
+    {
+        let mut builder = self.builder.clone();
+        let mut planner = self.planner.clone();
+        let cursor = intern(CURSOR);
+
+        let infer = async {
+            let mut program = anvil_parse::build_program(&mut builder, source).ok()?;
+            anvil_parse::bind_params(&mut program, &[]).ok()?;
+            let plan = planner.build(program, &mut builder).ok()?;
+            let ix = plan.node_indices()
+                .rfind(|ix| matches!(plan[*ix], ExecNode::Variable(name, _) if name == cursor))?;
+            let inferred = self.executor.infer(plan).await.ok()?;
+            inferred.node_schema(ix)
+        };
+        // Completion isn't async, wait for the plan on this thread
+        let schema = tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(infer));
+
+        schema
+            .map(|schema| schema.fields().iter().map(|f| f.name().clone()).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Candidates for the `word` ending `text` which follow from where it
+/// sits in a statement: tool names after '[', keyword arguments and
+/// `vars` within a tool's arguments and, inside expression strings,
+/// `$column` names of the input of the tool being edited. `columns`
+/// gives those of the data a script binds to `CURSOR`, the earlier
+/// statements of `text` followed by the flow into the tool. None if
+/// the word starts a flow, where what may appear is up to the caller.
+pub fn completions(
+    text: &str,
+    word: &str,
+    vars: &[String],
+    columns: impl FnOnce(&str) -> Vec<String>,
+) -> Option<Vec<String>>
+{
+    let scan = Scan::new(text);
+
+    if scan.in_comment {
+        return Some(vec![])
+    }
+
+    let preceding = &text[..text.len() - word.len()];
+    if scan.in_string {
+        if !preceding.ends_with('$') {
+            return Some(vec![])
+        }
+        let Some(input) = &scan.input else { return Some(vec![]) };
+        let columns = columns(input).into_iter()
+            .map(|col| if is_ident(&col) { col } else { format!("\"{col}\"") })
+            .collect();
+        return Some(columns)
+    }
+
+    if scan.naming {
+        return Some(tool_types().keys().map(|s| resolve(*s).to_string()).collect())
+    }
+
+    let tool = scan.tool?;
+    let kwargs = tool_types().get(&intern(&tool))
+        .map(|t| spec(*t).keyword)
+        .unwrap_or_default()
+        .iter()
+        .map(|arg| format!("{}=", arg.name));
+
+    Some(kwargs.chain(vars.iter().cloned()).collect())
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)>
+    {
+        let before = &line[..pos];
+        let start = before
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let word = &before[start..];
+
+        let mut candidates = self.candidates(before, word)
+            .into_iter()
+            .filter(|c| c.starts_with(word))
+            .collect::<Vec<_>>();
+        candidates.sort();
+        candidates.dedup();
+
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// Where the cursor sits in the statement being edited.
+#[derive(Default)]
+struct Scan {
+    in_string: bool,
+    in_comment: bool,
+    /// Typing a tool name just after '['.
+    naming: bool,
+    /// Tool whose arguments enclose the cursor.
+    tool: Option<String>,
+    /// Script binding the input of that tool to `CURSOR`, if it has
+    /// one.
+    input: Option<String>,
+}
+
+/// Tool brackets open within a flow, with where each opens, and where
+/// the flow starts.
+#[derive(Default)]
+struct FlowScope {
+    start: usize,
+    tools: Vec<(String, usize)>,
+    /// List literals open within the innermost tool's arguments
+    lists: usize,
+}
+
+impl FlowScope {
+    fn new(start: usize) -> FlowScope
+    {
+        FlowScope { start, ..Default::default() }
+    }
+}
+
+impl Scan {
+    fn new(text: &str) -> Scan
+    {
+        let mut scan = Scan::default();
+        let mut flows = vec![FlowScope::default()];
+        let mut word = String::new();
+        let mut strings = StringScan::default();
+        // Last character outside strings, comments and whitespace
+        let mut prev = None;
+        // Where the tool being named opens and the statement starts
+        let mut open = 0;
+        let mut statement = 0;
+
+        for (i, c) in text.char_indices() {
+            if scan.in_comment {
+                scan.in_comment = c != '\n';
+                continue;
+            }
+            if strings.step(&text[i..]) {
+                prev = Some('\'');
+                continue;
+            }
+            if c.is_alphanumeric() || c == '_' {
+                word.push(c);
+                prev = Some(c);
+                continue;
+            }
+
+            let flow = flows.last_mut().unwrap();
+            if scan.naming && !word.is_empty() {
+                flow.tools.push((std::mem::take(&mut word), open));
+                scan.naming = false;
+            }
+            word.clear();
+
+            match c {
+                '#'  => scan.in_comment = true,
+                // A bracket after ':', ',', '=' or another opens a list
+                '[' if matches!(prev, Some(':' | ',' | '=' | '[')) => flow.lists += 1,
+                '['  => { scan.naming = true; open = i; }
+                ']' if flow.lists > 0 => flow.lists -= 1,
+                ']'  => { flow.tools.pop(); }
+                '('  => flows.push(FlowScope::new(i + 1)),
+                ')' if flows.len() > 1 => { flows.pop(); }
+                ';'  => {
+                    statement = i + 1;
+                    flows = vec![FlowScope::new(statement)];
+                }
+                _ => {}
+            }
+            if !c.is_whitespace() && c != '#' {
+                prev = Some(c);
+            }
+        }
+
+        scan.in_string = strings.in_string();
+        let flow = flows.pop().unwrap_or_default();
+        if let Some((tool, open)) = flow.tools.last() {
+            scan.tool = Some(tool.clone());
+            scan.input = input(&text[..statement], &text[flow.start..*open]);
+        }
+
+        scan
+    }
+}
+
+/// Script of the statements `before` followed by one binding the flow
+/// into a tool, `flow`, to `CURSOR`. None if the tool starts its flow.
+fn input(before: &str, flow: &str) -> Option<String>
+{
+    let flow = flow.trim();
+    if let Some(flow) = flow.strip_suffix('|') {
+        Some(format!("{before}\n{} > {CURSOR};", flow.trim_end()))
+    } else if flow.ends_with("=>") {
+        // The target of a branch
+        Some(format!("{before}\n{flow} {CURSOR};"))
+    } else {
+        None
+    }
+}
+
+fn is_ident(s: &str) -> bool
+{
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use anvil_parse::Sources;
+    use super::*;
+    use crate::run;
+
+    fn input(text: &str) -> Option<String>
+    {
+        Scan::new(text).input
+    }
+
+    #[test]
+    fn input_of_piped_tool()
+    {
+        assert_eq!(input("x | [select: ['a']] | [filter: '$").unwrap(), "\nx | [select: ['a']] > __cursor;");
+        assert_eq!(input("a > y;\ny\n  | [filter: '$n > ").unwrap(), "a > y;\ny > __cursor;");
+    }
+
+    #[test]
+    fn input_of_flow_argument()
+    {
+        assert_eq!(input("[join: (x | [filter: '$").unwrap(), "\nx > __cursor;");
+        assert_eq!(input("y | [join: x, (y | [sort: 'a'] | [filter: '$").unwrap(), "\ny | [sort: 'a'] > __cursor;");
+    }
+
+    #[test]
+    fn input_of_branch()
+    {
+        assert_eq!(input("x | [filter: '$a > 1']: true => [select: '$").unwrap(), "\nx | [filter: '$a > 1']: true => __cursor;");
+    }
+
+    #[test]
+    fn no_input()
+    {
+        assert_eq!(input("[filter: '$"), None);
+        assert_eq!(input("[join: ([filter: '$"), None);
+        assert_eq!(input("x | [print]; "), None);
+    }
+
+    async fn helper(script: &str) -> ReplHelper
+    {
+        let mut builder = ASTBuilder::default();
+        let mut planner = Planner::default();
+        let mut executor = Executor::default();
+        run(&mut builder, &mut planner, &mut executor, &mut Sources::default(), script, Path::new(".")).await.unwrap();
+
+        let mut helper = ReplHelper::default();
+        ReplHelper::update(&mut helper, &builder, &planner, &executor);
+        helper
+    }
+
+    fn complete(helper: &ReplHelper, line: &str) -> Vec<String>
+    {
+        let mut candidates = helper.candidates(line, "");
+        candidates.sort();
+        candidates
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn columns_after_tools()
+    {
+        let helper = helper("[sql: 'SELECT 1 AS a, 2 AS b'] > t;").await;
+        assert_eq!(complete(&helper, "t | [filter: '$"), ["a", "b"]);
+        assert_eq!(complete(&helper, "t | [project: c='$a'] | [filter: '$"), ["c"]);
+        assert_eq!(complete(&helper, "t | [filter: '$a > 1']: true => [select: '$"), ["a", "b"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn columns_of_unrun_statements()
+    {
+        let helper = helper("").await;
+        let input = format!("[input: '{}/../../data/left.parquet']", env!("CARGO_MANIFEST_DIR"));
+        assert_eq!(complete(&helper, &format!("{input} | [select: ['id', 'email']] | [filter: '$")), ["email", "id"]);
+        assert_eq!(complete(&helper, &format!("{input} | [select: ['id']] > u; u | [filter: '$")), ["id"]);
+        assert_eq!(complete(&helper, "undefined | [filter: '$"), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use anvil_context::{intern, resolve, tool_types, Symbol};
+use anvil_parse::anvil::ast::*;
+use anvil_parse::{ASTBuilder, Locate};
+use crate::tools::did_you_mean;
+
+
+/// Check a definition before it's added: its name mustn't be a tool's
+/// and its branches may only bind its outputs, each exactly once.
+pub fn check_def(def: &Def) -> Result<()>
+{
+    let name = resolve(def.name);
+    if tool_types().contains_key(&def.name) {
+        return Err(anyhow!("can't define '{name}', there is a tool of that name")).at(def.span)
+    }
+
+    let mut bound = vec![];
+    for branch in def.branches.iter().flatten() {
+        let binding = match &branch.target {
+            Target::Variable(output, span) => Some((*output, *span)),
+            Target::Flow { variable, .. } => *variable,
+        };
+        let Some((output, span)) = binding else { continue };
+
+        if !def.outputs.contains(&output) {
+            return Err(anyhow!("{name} can only bind its outputs, '{}' isn't one", resolve(output))).at(span)
+        }
+        if bound.contains(&output) {
+            return Err(anyhow!("{name} binds output '{}' more than once", resolve(output))).at(span)
+        }
+        bound.push(output);
+    }
+
+    if let Some(output) = def.outputs.iter().find(|output| !bound.contains(output)) {
+        return Err(anyhow!("{name} doesn't bind its output '{}'", resolve(*output))).at(def.span)
+    }
+
+    Ok(())
+}
+
+/// The arguments of a use of a definition by the parameter they're
+/// given for, omitted ones taking their defaults.
+pub fn bind_args(def: &Def, tr: &ToolRef) -> Result<HashMap<Symbol, ArgValue>>
+{
+    let name = resolve(def.name);
+    let mut args = HashMap::new();
+    let mut positional = def.params.iter();
+
+    for arg in &tr.args {
+        let (param, value) = match arg {
+            ToolArg::Positional(value, _) => {
+                let (param, _) = positional.next()
+                    .ok_or_else(|| anyhow!("{name} takes at most {} positional argument(s)", def.params.len()))?;
+                (*param, value)
+            }
+            ToolArg::Keyword { ident, value, .. } => {
+                if !def.params.iter().any(|(param, _)| param == ident) {
+                    let names = def.params.iter().map(|(param, _)| resolve(*param));
+                    let key = resolve(*ident);
+                    return Err(anyhow!("unexpected named argument '{key}' for {name}{}", did_you_mean(key, names)))
+                }
+                (*ident, value)
+            }
+        };
+        if args.insert(param, value.clone()).is_some() {
+            return Err(anyhow!("{name} argument '{}' is given more than once", resolve(param)))
+        }
+    }
+
+    for (param, default) in &def.params {
+        if args.contains_key(param) {
+            continue
+        }
+        let default = default.clone()
+            .ok_or_else(|| anyhow!("{name} missing required argument '{}'", resolve(*param)))?;
+        args.insert(*param, default);
+    }
+
+    Ok(args)
+}
+
+/// The flow and branches of a definition for one use of it, with the
+/// arguments in place of the parameters and fresh ids for its tools.
+pub fn instantiate(
+    def: &Def,
+    args: &HashMap<Symbol, ArgValue>,
+    builder: &mut ASTBuilder,
+) -> Result<(Flow, Option<Vec<Branch>>)>
+{
+    let flow = substitute_flow(&def.flow, args, builder)?;
+
+    let mut branches = def.branches.clone();
+    for branch in branches.iter_mut().flatten() {
+        if let Target::Flow { flow, .. } = &mut branch.target {
+            *flow = substitute_flow(flow, args, builder)?;
+        }
+    }
+
+    Ok((flow, branches))
+}
+
+/// A flow of a definition with its tools given fresh ids and the
+/// parameters it uses replaced. A parameter used as an item of the
+/// flow is replaced by the flow or variable given for it, one used as
+/// a tool argument by whatever was given.
+fn substitute_flow(flow: &Flow, args: &HashMap<Symbol, ArgValue>, builder: &mut ASTBuilder) -> Result<Flow>
+{
+    let mut items = vec![];
+
+    for item in &flow.items {
+        match item {
+            FlowItem::Variable(name, span) => match args.get(name) {
+                None => items.push(item.clone()),
+                Some(ArgValue::Flow(arg)) => items.extend(arg.items.iter().cloned()),
+                Some(ArgValue::Ident(var, span)) => items.push(FlowItem::Variable(intern(var), *span)),
+                Some(_) => {
+                    return Err(anyhow!("'{}' is used as a flow, its argument must be a flow or variable", resolve(*name))).at(*span)
+                }
+            },
+            FlowItem::Tool(tool) => {
+                let mut tool = tool.clone();
+                tool.id = builder.next_id();
+                for arg in &mut tool.args {
+                    substitute_value(arg.value_mut(), args, builder)?;
+                }
+                items.push(FlowItem::Tool(tool));
+            }
+        }
+    }
+
+    Ok(Flow { items, span: flow.span })
+}
+
+fn substitute_value(value: &mut ArgValue, args: &HashMap<Symbol, ArgValue>, builder: &mut ASTBuilder) -> Result<()>
+{
+    match value {
+        ArgValue::Flow(flow) => *flow = substitute_flow(flow, args, builder)?,
+        ArgValue::Ident(name, _) => {
+            if let Some(arg) = args.get(&intern(name)) {
+                *value = arg.clone();
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
@@ -1,99 +1,448 @@
 #![allow(dead_code, unused)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::thread::available_parallelism;
 
 use anyhow::{anyhow, Result};
+use datafusion::catalog::TableProvider;
 use datafusion::prelude::{DataFrame, SessionContext};
 use petgraph::{
     Incoming,
     algo::toposort,
-    graph::NodeIndex,
+    graph::{EdgeReference, NodeIndex},
     visit::EdgeRef,
 };
+use tokio::task::JoinSet;
 
-use anvil_context::{resolve, syms};
-use crate::{ExecutionPlan, ExecNode};
-use crate::tools::{tool, Values};
+use anvil_context::{resolve, syms, Symbol};
+use anvil_parse::{Diagnostics, Locate};
+use crate::{EdgeKind, ExecutionPlan, ExecEdge, ExecNode};
+use crate::infer::{infer, Inferred};
+use crate::tools::{tool, Resource, Tool, Values};
 
 type Inputs = HashMap<NodeIndex, Values>;
 
-#[derive(Default)]
+/// State of an executor before a run, see [`Executor::snapshot`].
+pub struct Snapshot {
+    dfs: Inputs,
+    done: HashSet<NodeIndex>,
+    tables: Vec<(String, Option<Arc<dyn TableProvider>>)>,
+}
+
+#[derive(Clone)]
 pub struct Executor {
     ctx: SessionContext,
     dfs: Inputs,
+    concurrency: usize,
+    auto_cache: bool,
+    done: HashSet<NodeIndex>,
+}
+
+impl Default for Executor {
+    fn default() -> Self
+    {
+        let concurrency = available_parallelism().map(|n| n.get()).unwrap_or(1);
+        Executor::new(concurrency)
+    }
 }
 
 impl Executor {
+    /// Create an executor running at most `concurrency` tools at once.
+    pub fn new(concurrency: usize) -> Self
+    {
+        Executor {
+            ctx: SessionContext::default(),
+            dfs: Inputs::default(),
+            concurrency: concurrency.max(1),
+            auto_cache: false,
+            done: HashSet::new(),
+        }
+    }
+
+    /// Materialize outputs read by more than one consumer so the
+    /// upstream plan only executes once.
+    pub fn set_auto_cache(&mut self, enabled: bool)
+    {
+        self.auto_cache = enabled;
+    }
+
+    /// Discard all results and registered tables.
     pub fn reset(&mut self)
     {
         self.ctx = SessionContext::default();
+        self.dfs.clear();
+        self.done.clear();
     }
 
-    pub async fn run(&mut self, plan: &ExecutionPlan) -> Result<()>
+    /// Result held by a variable node once it has run.
+    pub fn values(&self, ix: NodeIndex) -> Option<&Values>
     {
-        let nodes = match toposort(plan, None) {
-            Ok(nodes)  => nodes,
-            Err(cycle) => return Err(anyhow!("cycle detected at node {:?}", cycle.node_id()))
-        };
+        self.dfs.get(&ix).filter(|_| self.done.contains(&ix))
+    }
 
-        // Separate the nodes into pure sources and nodes requiring
-        // input in one way or another (pipeline, argument, variable).
-        let (sources, sinks) = nodes.iter()
-            .fold((vec![], vec![]), |(mut src, mut nds), ix| {
-                if plan[*ix].is_source() { src.push(*ix) } else { nds.push(*ix) }
-                (src, nds)
-            });
+    pub fn drop_values(&mut self, ix: NodeIndex) -> Option<Values>
+    {
+        self.dfs.remove(&ix)
+    }
 
-        // Run source tools first
-        self.exec_nodes(&sources, plan).await?;
-        self.exec_nodes(&sinks, plan).await?;
+    /// Results of variables and the tables the plan's tools are yet to
+    /// register, as they are before running it.
+    pub async fn snapshot(&self, plan: &ExecutionPlan) -> Snapshot
+    {
+        let mut tables = vec![];
+        for ix in plan.node_indices().filter(|ix| !self.done.contains(ix)) {
+            let ExecNode::Tool(tool, ..) = &plan[ix] else { continue };
+            for resource in tool.produces() {
+                if let Resource::Table(name) = resource {
+                    let table = self.ctx.table_provider(name.as_str()).await.ok();
+                    tables.push((name, table));
+                }
+            }
+        }
+
+        Snapshot { dfs: self.dfs.clone(), done: self.done.clone(), tables }
+    }
+
+    /// Put back what a run which failed changed. Files it wrote are
+    /// left as they are.
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<()>
+    {
+        for (name, table) in snapshot.tables.into_iter().rev() {
+            self.ctx.deregister_table(name.as_str())?;
+            if let Some(table) = table {
+                self.ctx.register_table(name.as_str(), table)?;
+            }
+        }
+        self.dfs = snapshot.dfs;
+        self.done = snapshot.done;
 
         Ok(())
     }
 
+    /// Outputs of every node of the plan built without executing it,
+    /// using the results of variables set by earlier runs.
+    pub async fn infer(&self, plan: &ExecutionPlan) -> Result<Inferred>
+    {
+        let nodes = sorted(plan)?;
+        Ok(infer(plan, &nodes, &self.dfs, &self.ctx).await)
+    }
+
+    pub async fn run(&mut self, plan: &ExecutionPlan) -> Result<()>
+    {
+        let nodes = sorted(plan)?;
+
+        // Plans grow as statements are added, only run the nodes added
+        // since the last run along with any variables they rebind.
+        let nodes = nodes.into_iter()
+            .filter(|ix| {
+                !self.done.contains(ix) ||
+                plan.neighbors_directed(*ix, Incoming).any(|src| !self.done.contains(&src))
+            })
+            .collect::<Vec<_>>();
+
+        // Check every tool's arguments against the schema of its input
+        // before running anything.
+        let inferred = infer(plan, &nodes, &self.dfs, &self.ctx).await;
+        if inferred.problems.is_empty() {
+            self.exec_nodes(&nodes, plan).await
+        } else {
+            Err(Diagnostics(inferred.problems).into())
+        }
+    }
+
     async fn exec_nodes(&mut self, nodes: &[NodeIndex], plan: &ExecutionPlan) -> Result<()>
     {
+        // A node is ready to run once every incoming edge, data or
+        // ordering, from a node in this run has had its source complete.
+        let mut pending = nodes.iter()
+            .map(|ix| (*ix, 0))
+            .collect::<HashMap<_, _>>();
         for ix in nodes {
-            let inputs  = self.dfs.remove(ix);
-            let outputs = match &plan[*ix] {
-                ExecNode::Tool(tool) => {
-                    tool.run(inputs, &self.ctx).await?
-                },
-                ExecNode::Variable(name) => {
-                    if let Some(values) = inputs {
-                        self.dfs.insert(*ix, values.clone());
-                        values
-                    } else {
-                        return Err(anyhow!("uninitialized variable: {}", resolve(*name)))
+            for edge in plan.edges_directed(*ix, Incoming) {
+                let src = edge.source();
+                if pending.contains_key(&src) {
+                    *pending.get_mut(ix).unwrap() += 1;
+                } else if let ExecNode::Variable(..) = &plan[src] {
+                    // Variable bound by an earlier run
+                    if let Some(values) = self.dfs.get(&src).cloned() {
+                        self.send(edge, &values, plan);
                     }
                 }
-            };
+            }
+        }
 
-            if !outputs.dfs.is_empty() {
-                for edge in plan.edges(*ix) {
-                    let e = edge.weight();
-                    let t = edge.target();
-                    let node = &plan[t];
-
-                    let default = syms().default;
-                    let mut v = self.dfs.entry(t).or_default();
-                    for (p, df) in &outputs.dfs {
-                        match node {
-                            ExecNode::Tool(tool) => {
-                                if *p == e.port || *p == default || e.port == default {
-                                    v.set(e.port, df.clone())
-                                }
-                            }
-                            ExecNode::Variable(_) => {
-                                v.set(default, df.clone())
-                            }
-                        }
-                    }
-                }
+        let mut ready = nodes.iter()
+            .filter(|ix| pending[ix] == 0)
+            .cloned()
+            .collect::<VecDeque<_>>();
+
+        let mut tasks = JoinSet::new();
+        while !ready.is_empty() || !tasks.is_empty() {
+            while tasks.len() < self.concurrency && let Some(ix) = ready.pop_front() {
+                let inputs = self.dfs.remove(&ix);
+                let ports  = if self.auto_cache { data_ports(plan, ix) } else { vec![] };
+                let ctx    = self.ctx.clone();
+                let node   = plan[ix].clone();
+
+                tasks.spawn(async move { (ix, exec_node(node, inputs, ctx, ports).await) });
+            }
+
+            if let Some(result) = tasks.join_next().await {
+                let (ix, outputs) = result?;
+                self.complete(ix, outputs?, plan, &mut pending, &mut ready);
             }
         }
 
         Ok(())
     }
 
+    /// Pass a finished node's outputs along its edges and queue any
+    /// targets which are no longer waiting on other nodes.
+    fn complete(
+        &mut self,
+        ix: NodeIndex,
+        outputs: Values,
+        plan: &ExecutionPlan,
+        pending: &mut HashMap<NodeIndex, usize>,
+        ready: &mut VecDeque<NodeIndex>,
+    )
+    {
+        self.done.insert(ix);
+        if let ExecNode::Variable(..) = &plan[ix] {
+            self.dfs.insert(ix, outputs.clone());
+        }
+
+        for edge in plan.edges(ix) {
+            let t = edge.target();
+            let Some(n) = pending.get_mut(&t) else {
+                // Consumer from an earlier run
+                continue
+            };
+
+            *n -= 1;
+            if *n == 0 {
+                ready.push_back(t);
+            }
+            self.send(edge, &outputs, plan);
+        }
+    }
+
+    /// Set the outputs of an edge's source as inputs of its target.
+    fn send(&mut self, edge: EdgeReference<ExecEdge>, outputs: &Values, plan: &ExecutionPlan)
+    {
+        if edge.weight().kind == EdgeKind::Order || outputs.dfs.is_empty() {
+            return
+        }
+
+        route(plan, edge, outputs, self.dfs.entry(edge.target()).or_default());
+    }
+}
+
+/// Add the outputs of an edge's source carried by the edge to the
+/// inputs of its target.
+pub fn route(plan: &ExecutionPlan, edge: EdgeReference<ExecEdge>, outputs: &Values, inputs: &mut Values)
+{
+    let default = syms().default;
+    let port = read_port(plan, edge);
+
+    for (p, df) in &outputs.dfs {
+        if *p != port && *p != default {
+            continue
+        }
+        match &plan[edge.target()] {
+            ExecNode::Tool(..)     => inputs.set(edge.weight().port, df.clone()),
+            ExecNode::Variable(..) => inputs.set(default, df.clone()),
+        }
+    }
+}
+
+/// Output of its source an edge reads. An edge on a port the source
+/// has no output for, the default port or the input port of a flow
+/// argument, carries its first output, e.g. the true rows of a filter.
+fn read_port(plan: &ExecutionPlan, edge: EdgeReference<ExecEdge>) -> Symbol
+{
+    let port = edge.weight().port;
+    let outputs = match &plan[edge.source()] {
+        ExecNode::Tool(tool, ..) => tool.outputs(),
+        ExecNode::Variable(..)   => vec![syms().default],
+    };
+
+    if outputs.contains(&port) {
+        port
+    } else {
+        outputs.first().copied().unwrap_or(syms().default)
+    }
+}
+
+fn sorted(plan: &ExecutionPlan) -> Result<Vec<NodeIndex>>
+{
+    toposort(plan, None)
+        .map_err(|cycle| anyhow!("cycle detected at node {:?}", cycle.node_id()))
+}
+
+async fn exec_node(
+    node: ExecNode,
+    inputs: Option<Values>,
+    ctx: SessionContext,
+    ports: Vec<Symbol>,
+) -> Result<Values>
+{
+    let span = node.span();
+    let outputs = match &node {
+        ExecNode::Tool(tool, ..) => {
+            // Each output of a tool with several is planned over its
+            // input, cache that so it's read once rather than per output
+            let inputs = match inputs {
+                Some(inputs) if reads_outputs(tool, &ports) > 1 => {
+                    Some(cache_all(inputs).await.map_err(|e| node.locate(e))?)
+                }
+                inputs => inputs,
+            };
+            tool.run(inputs, &ctx).await.map_err(|e| node.locate(e))?
+        }
+        ExecNode::Variable(name, _) => {
+            inputs.ok_or_else(|| anyhow!("uninitialized variable: {}", resolve(*name))).at(span)?
+        }
+    };
+
+    cache_shared(outputs, &ports).await.at(span)
+}
+
+/// Outputs read by the data edges leaving a node.
+fn data_ports(plan: &ExecutionPlan, ix: NodeIndex) -> Vec<Symbol>
+{
+    plan.edges(ix)
+        .filter(|e| e.weight().kind == EdgeKind::Data)
+        .map(|e| read_port(plan, e))
+        .collect()
+}
+
+/// Number of distinct outputs of a tool read by the data edges
+/// reading `ports`.
+fn reads_outputs(tool: &Tool, ports: &[Symbol]) -> usize
+{
+    let outputs = tool.outputs();
+
+    ports.iter()
+        .filter(|p| outputs.contains(p))
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+async fn cache_all(values: Values) -> Result<Values>
+{
+    let mut cached = Values::default();
+    for (p, df) in values.dfs {
+        cached.set(p, df.cache().await?);
+    }
+
+    Ok(cached)
+}
+
+/// Cache each output dataframe read by more than one data edge,
+/// `ports` being the outputs the edges read.
+async fn cache_shared(outputs: Values, ports: &[Symbol]) -> Result<Values>
+{
+    let mut values = Values::default();
+    for (p, df) in outputs.dfs {
+        let n = ports.iter().filter(|e| **e == p).count();
+        let df = if n > 1 { df.cache().await? } else { df };
+        values.set(p, df);
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use datafusion::logical_expr::LogicalPlan;
+    use datafusion::prelude::SessionContext;
+
+    use anvil_context::intern;
+    use anvil_parse::{ASTBuilder, Sources};
+    use crate::{run, Executor, Planner};
+    use crate::tools::Values;
+    use super::cache_shared;
+
+    async fn rows(script: &str, var: &str) -> usize
+    {
+        let mut builder = ASTBuilder::default();
+        let mut planner = Planner::default();
+        let mut executor = Executor::default();
+        run(&mut builder, &mut planner, &mut executor, &mut Sources::default(), script, Path::new(".")).await.unwrap();
+
+        let ix = planner.vars()[&intern(var)];
+        let df = executor.values(ix).unwrap().get_one().unwrap().clone();
+        df.count().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn flow_argument_reads_first_output()
+    {
+        let script = "
+            [join:
+              ([sql: 'SELECT * FROM (VALUES (1), (2), (3)) AS t(id)'] | [filter: '$id > 1']),
+              ([sql: 'SELECT * FROM (VALUES (2), (3)) AS t(r)'] | [route: two='$r == 2']),
+              cols_lt=['id'],
+              cols_rt=['r']
+            ] > joined;
+        ";
+        assert_eq!(rows(script, "joined").await, 1);
+    }
+
+    #[tokio::test]
+    async fn sql_table_argument_reads_first_output()
+    {
+        let script = "
+            [sql: 'SELECT * FROM a', a=([sql: 'SELECT * FROM (VALUES (1), (2)) AS t(id)'] | [filter: '$id == 2'])] > b;
+        ";
+        assert_eq!(rows(script, "b").await, 1);
+    }
+
+    #[tokio::test]
+    async fn cache_shared_outputs_only()
+    {
+        let ctx = SessionContext::default();
+        let df = ctx.sql("SELECT * FROM (VALUES (1)) AS t(id)").await.unwrap();
+        let (t, f) = (intern("true"), intern("false"));
+        let mut outputs = Values::default();
+        outputs.set(t, df.clone());
+        outputs.set(f, df);
+
+        let cached = |values: &Values, port| matches!(values.dfs[&port].logical_plan(), LogicalPlan::TableScan(_));
+
+        let values = cache_shared(outputs.clone(), &[t, f]).await.unwrap();
+        assert!(!cached(&values, t) && !cached(&values, f));
+        let values = cache_shared(outputs, &[t, f, t]).await.unwrap();
+        assert!(cached(&values, t) && !cached(&values, f));
+    }
+
+    #[tokio::test]
+    async fn failed_run_restores_variables_and_tables()
+    {
+        let mut builder = ASTBuilder::default();
+        let mut planner = Planner::default();
+        let mut executor = Executor::default();
+        let mut sources = Sources::default();
+        let path = Path::new(".");
+
+        let first = "[sql: 'SELECT * FROM (VALUES (1)) AS t(id)'] > x;";
+        run(&mut builder, &mut planner, &mut executor, &mut sources, first, path).await.unwrap();
+
+        let failing = "
+            [sql: 'SELECT * FROM (VALUES (1), (2)) AS t(id)'] > x;
+            x | [register: table='u'];
+            [sql: 'SELECT nope FROM u'] > y;
+        ";
+        assert!(run(&mut builder, &mut planner, &mut executor, &mut sources, failing, path).await.is_err());
+        assert!(!planner.vars().contains_key(&intern("y")));
+        assert!(!executor.ctx.table_exist("u").unwrap());
+
+        let ix = planner.vars()[&intern("x")];
+        let df = executor.values(ix).unwrap().get_one().unwrap().clone();
+        assert_eq!(df.count().await.unwrap(), 1);
+    }
 }
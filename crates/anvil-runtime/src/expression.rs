@@ -3,11 +3,17 @@ use std::sync::{Arc, LazyLock};
 
 use anyhow::{anyhow, Result};
 use datafusion::prelude::*;
-use datafusion::logical_expr::{Expr, Operator, ScalarUDF};
+use datafusion::logical_expr::{
+    AggregateUDF, Expr, Operator, ScalarUDF, WindowFunctionDefinition, WindowUDF,
+};
+use datafusion::logical_expr::expr::WindowFunction;
+use datafusion::logical_expr::utils::COUNT_STAR_EXPANSION;
 
 use anvil_parse::expr::ast;
 
 type Funcs = HashMap<String, Arc<ScalarUDF>>;
+type Aggregates = HashMap<String, Arc<AggregateUDF>>;
+type Windows = HashMap<String, Arc<WindowUDF>>;
 
 static FUNCTIONS: LazyLock<Funcs> = LazyLock::new(|| {
     datafusion::functions::all_default_functions()
@@ -16,6 +22,20 @@ static FUNCTIONS: LazyLock<Funcs> = LazyLock::new(|| {
         .collect::<Funcs>()
 });
 
+static AGGREGATES: LazyLock<Aggregates> = LazyLock::new(|| {
+    datafusion::functions_aggregate::all_default_aggregate_functions()
+        .iter()
+        .map(|f| (f.name().to_string(), f.clone()))
+        .collect::<Aggregates>()
+});
+
+static WINDOWS: LazyLock<Windows> = LazyLock::new(|| {
+    datafusion::functions_window::all_default_window_functions()
+        .iter()
+        .map(|f| (f.name().to_string(), f.clone()))
+        .collect::<Windows>()
+});
+
 pub fn eval_expression(expr: &ast::Expr) -> Result<Expr>
 {
     let expr = match expr {
@@ -48,11 +68,35 @@ pub fn eval_expression(expr: &ast::Expr) -> Result<Expr>
             let expr = eval_expression(value)?;
             expr.alias(target)
         }
+        ast::Expr::Wildcard => {
+            return Err(anyhow!("'*' can only be the argument of count(*)"))
+        }
     };
 
     Ok(expr)
 }
 
+/// Evaluate a function call as a window function. Aggregate
+/// functions are also accepted, e.g. sum() for running totals.
+pub fn eval_window_function(expr: &ast::Expr) -> Result<Expr>
+{
+    let ast::Expr::Call { name, args } = expr else {
+        return Err(anyhow!("window expression must be a function call"))
+    };
+
+    let args = eval_args(name, args)?;
+
+    let func = if let Some(func) = WINDOWS.get(name) {
+        WindowFunctionDefinition::WindowUDF(func.clone())
+    } else if let Some(func) = AGGREGATES.get(name) {
+        WindowFunctionDefinition::AggregateUDF(func.clone())
+    } else {
+        return Err(anyhow!("unknown window function '{name}'"))
+    };
+
+    Ok(Expr::from(WindowFunction::new(func, args)))
+}
+
 fn eval_literal(litval: &ast::Literal) -> Expr
 {
     match litval {
@@ -86,12 +130,28 @@ fn eval_binary_op(op: ast::BinaryOp) -> Operator
 
 fn eval_function_call(name: &str, args: &[ast::Expr]) -> Result<Expr>
 {
-    let args = args.iter()
-        .map(eval_expression)
-        .collect::<Result<Vec<_>>>()?;
+    let args = eval_args(name, args)?;
 
-    match FUNCTIONS.get(name) {
-        Some(func) => Ok(func.call(args)),
-        None => Err(anyhow!("unknown function '{name}'"))
+    if let Some(func) = FUNCTIONS.get(name) {
+        Ok(func.call(args))
+    } else if let Some(func) = AGGREGATES.get(name) {
+        Ok(func.call(args))
+    } else {
+        Err(anyhow!("unknown function '{name}'"))
     }
 }
+
+/// Arguments of a function call. `*` is only accepted as the sole
+/// argument of count, where it counts every row.
+fn eval_args(name: &str, args: &[ast::Expr]) -> Result<Vec<Expr>>
+{
+    if let [ast::Expr::Wildcard] = args
+        && name == "count"
+    {
+        return Ok(vec![lit(COUNT_STAR_EXPANSION)])
+    }
+
+    args.iter()
+        .map(eval_expression)
+        .collect()
+}
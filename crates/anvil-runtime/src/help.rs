@@ -0,0 +1,32 @@
+use anyhow::{anyhow, Result};
+
+use anvil_context::{intern, resolve, tool_types};
+use crate::tools::{spec, ToolSpec};
+
+/// One line summary of every tool.
+pub fn tools_help() -> String
+{
+    let mut tools = tool_types().iter()
+        .map(|(name, ty)| (resolve(*name), spec(*ty).description))
+        .collect::<Vec<_>>();
+    tools.sort();
+
+    tools.iter()
+        .map(|(name, description)| format!("  {name:<10} {description}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Description, arguments and ports of a tool.
+pub fn tool_help(name: &str) -> Result<String>
+{
+    let spec = tool_spec(name).ok_or_else(|| anyhow!("unknown tool: {name}"))?;
+
+    Ok(spec.to_string())
+}
+
+/// Spec of the tool with the given name.
+pub fn tool_spec(name: &str) -> Option<&'static ToolSpec>
+{
+    tool_types().get(&intern(name)).map(|ty| spec(*ty))
+}
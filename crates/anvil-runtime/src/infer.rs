@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use datafusion::common::DFSchemaRef;
+use datafusion::prelude::SessionContext;
+use petgraph::graph::{EdgeReference, NodeIndex};
+use petgraph::visit::EdgeRef;
+use petgraph::Direction::Incoming;
+
+use anvil_parse::Diagnostic;
+use crate::executor::route;
+use crate::tools::Values;
+use crate::{EdgeKind, ExecEdge, ExecNode, ExecutionPlan};
+
+/// Outputs of plan nodes built without executing the plan, from which
+/// the schema flowing along each edge can be read.
+#[derive(Default)]
+pub struct Inferred {
+    outputs: HashMap<NodeIndex, Values>,
+    /// Tools whose arguments don't fit their input, e.g. a filter
+    /// referencing a column which doesn't exist.
+    pub problems: Vec<Diagnostic>,
+}
+
+impl Inferred {
+    /// Schema of the data carried by an edge, if known.
+    pub fn schema(&self, plan: &ExecutionPlan, edge: EdgeReference<ExecEdge>) -> Option<DFSchemaRef>
+    {
+        if edge.weight().kind == EdgeKind::Order {
+            return None
+        }
+
+        let mut inputs = Values::default();
+        route(plan, edge, self.outputs.get(&edge.source())?, &mut inputs);
+        inputs.get_one().map(|df| df.logical_plan().schema().clone())
+    }
+
+    /// Schema of the data a node outputs, if known and it has one.
+    pub fn node_schema(&self, ix: NodeIndex) -> Option<DFSchemaRef>
+    {
+        self.outputs.get(&ix)?.get_one().map(|df| df.logical_plan().schema().clone())
+    }
+}
+
+/// Infer the outputs of `nodes`, given in topological order. Inputs
+/// from nodes outside the list are taken from `known`. Nodes with an
+/// input which can't be inferred are skipped.
+pub async fn infer(
+    plan: &ExecutionPlan,
+    nodes: &[NodeIndex],
+    known: &HashMap<NodeIndex, Values>,
+    ctx: &SessionContext,
+) -> Inferred
+{
+    let mut inferred = Inferred::default();
+    let mut problems = vec![];
+
+    'nodes: for ix in nodes {
+        let mut inputs: Option<Values> = None;
+        for edge in plan.edges_directed(*ix, Incoming) {
+            if edge.weight().kind == EdgeKind::Order {
+                continue
+            }
+            let outputs = match inferred.outputs.get(&edge.source()) {
+                Some(outputs) => outputs,
+                None => match known.get(&edge.source()) {
+                    Some(outputs) => outputs,
+                    None => continue 'nodes,
+                }
+            };
+            route(plan, edge, outputs, inputs.get_or_insert_default());
+        }
+
+        let outputs = match &plan[*ix] {
+            ExecNode::Tool(tool, span, _) => match tool.infer(inputs, ctx).await {
+                Ok(outputs) => outputs,
+                Err(e) => {
+                    let message = format!("{}: {e}", plan[*ix]);
+                    let mut diagnostics = vec![Diagnostic { message, span: *span }];
+                    diagnostics.extend(plan[*ix].uses());
+                    problems.push((*span, diagnostics));
+                    None
+                }
+            },
+            ExecNode::Variable(..) => inputs,
+        };
+
+        if let Some(outputs) = outputs {
+            inferred.outputs.insert(*ix, outputs);
+        }
+    }
+
+    // Report problems in the order they're written, each followed by
+    // where the definitions of its tool were used
+    problems.sort_by_key(|(span, _)| (span.source, span.start));
+    inferred.problems = problems.into_iter().flat_map(|(_, d)| d).collect();
+
+    inferred
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use anvil_parse::{ASTBuilder, Sources};
+    use crate::{run, Executor, Planner};
+
+    #[tokio::test]
+    async fn problems_in_script_order()
+    {
+        let script = format!("
+            [input: '{}/../../data/left.parquet'] > a;
+            a | [filter: '$one > 1'] | [filter: '$two > 1'] > b;
+            a | [filter: '$three > 1'] > c;
+            a | [filter: '$four > 1'] > d;
+        ", env!("CARGO_MANIFEST_DIR"));
+        let mut builder = ASTBuilder::default();
+        let mut planner = Planner::default();
+        let mut executor = Executor::default();
+        let e = run(&mut builder, &mut planner, &mut executor, &mut Sources::default(), &script, Path::new("."))
+            .await
+            .unwrap_err()
+            .to_string();
+
+        let at = |name| e.find(name).unwrap();
+        assert!(at("one") < at("three") && at("three") < at("four"));
+    }
+}
@@ -1,38 +1,65 @@
+use std::path::Path;
+
 use anyhow::Result;
 
+mod completion;
+mod defs;
 mod executor;
 mod expression;
+mod help;
+mod infer;
 mod planner;
 mod repl;
 mod tools;
+mod validate;
 
-use anvil_parse::ASTBuilder;
+use anvil_parse::anvil::ast::Program;
+use anvil_parse::{ASTBuilder, Sources};
 
+pub use completion::completions;
 pub use executor::Executor;
-pub use expression::eval_expression;
-pub use planner::{ExecutionPlan, ExecEdge, ExecNode, Planner};
+pub use expression::{eval_expression, eval_window_function};
+pub use help::{tool_help, tool_spec, tools_help};
+pub use infer::Inferred;
+pub use planner::{EdgeKind, ExecutionPlan, ExecEdge, ExecNode, Planner};
 pub use repl::run_repl;
+pub use tools::{ArgSpec, ToolSpec};
 
+/// Run a script, or statements typed at the REPL, with `path` where
+/// its imports are found relative to. The scripts it imports are added
+/// to `sources`.
 pub async fn run(
     builder: &mut ASTBuilder,
     planner: &mut Planner,
     executor: &mut Executor,
-    input: &str
+    sources: &mut Sources,
+    input: &str,
+    path: &Path,
 ) -> Result<()>
 {
-    let program = anvil_parse::build_program(builder, input)?;
-    let plan = planner.build(program)?;
-    executor.run(plan).await
+    let mut program = anvil_parse::build_program(builder, input)?;
+    anvil_parse::load_imports(builder, &mut program, path, sources)?;
+    anvil_parse::bind_params(&mut program, &[])?;
+    plan_and_run(builder, planner, executor, program).await
 }
 
-pub async fn run_stmt(
+/// Plan a program and run it. A program which fails to run is taken
+/// out of the plan again, and variables it rebound and tables it
+/// registered are put back as they were.
+async fn plan_and_run(
     builder: &mut ASTBuilder,
     planner: &mut Planner,
     executor: &mut Executor,
-    input: &str
+    program: Program,
 ) -> Result<()>
 {
-    let stmt = anvil_parse::build_statement(builder, input)?;
-    let plan = planner.build_statement(stmt)?;
-    executor.run(plan).await
-}
\ No newline at end of file
+    let plan = planner.build(program, builder)?;
+    let snapshot = executor.snapshot(plan).await;
+    if let Err(e) = executor.run(plan).await {
+        planner.rollback();
+        executor.restore(snapshot)?;
+        return Err(e)
+    }
+
+    Ok(())
+}
@@ -1,74 +1,175 @@
 use core::default::Default;
 use std::collections::HashMap;
 use std::fmt;
+use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
-use petgraph::graph::{Graph, NodeIndex};
+use petgraph::graph::{EdgeIndex, Graph, NodeIndex};
 use string_interner::symbol::Symbol as Sym;
 
 use anvil_context::{resolve, syms, Symbol};
 use anvil_parse::anvil::ast::*;
-use crate::tools::Tool;
+use anvil_parse::{locate, ASTBuilder, Diagnostic, Diagnostics, Locate};
+use crate::defs::{bind_args, check_def, instantiate};
+use crate::tools::{did_you_mean, Resource, Tool};
+use crate::validate::validate;
 
 pub type ExecutionPlan = Graph<ExecNode, ExecEdge>;
 
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Planner {
     plan: ExecutionPlan,
     vars: HashMap<Symbol, NodeIndex>,
     tools: HashMap<ToolId, NodeIndex>,
+    resources: HashMap<Resource, NodeIndex>,
+    defs: HashMap<Symbol, Def>,
+    /// Definitions being planned where they're used, innermost last
+    expanding: Vec<(Symbol, Span)>,
+    /// State before the last build, to take it out again
+    last: Option<Checkpoint>,
 }
 
 impl Planner {
-    pub fn build(&mut self, program: Program) -> Result<&ExecutionPlan>
+    /// Add a program's definitions and statements to the plan and
+    /// validate them. The plan is left unchanged if any statement
+    /// fails. Tools of a definition are given ids by `builder` each
+    /// time it's used.
+    pub fn build(&mut self, program: Program, builder: &mut ASTBuilder) -> Result<&ExecutionPlan>
     {
-        for stmt in program.statements {
-            self.build_statement(stmt)?;
+        let checkpoint = self.checkpoint();
+        let result = program.defs.into_iter()
+            .try_for_each(|def| self.add_def(def))
+            .and_then(|_| program.statements.iter().try_for_each(|stmt| self.add_statement(stmt, builder)))
+            .and_then(|_| validate(&self.plan, checkpoint.nodes, checkpoint.edges));
+
+        if let Err(e) = result {
+            self.restore(checkpoint);
+            return Err(e)
         }
+        self.last = Some(checkpoint);
 
         Ok(&self.plan)
     }
 
-    pub fn build_statement(&mut self, stmt: Statement) -> Result<&ExecutionPlan>
+    /// Remove what the last build added to the plan, e.g. once running
+    /// it has failed.
+    pub fn rollback(&mut self)
     {
-        let ix = self.build_flow(&stmt.flow, syms().default, None)?;
+        if let Some(checkpoint) = self.last.take() {
+            self.restore(checkpoint);
+        }
+    }
+
+    pub fn reset(&mut self)
+    {
+        *self = Planner::default();
+    }
+
+    /// Variables bound so far and the plan nodes holding them.
+    pub fn vars(&self) -> &HashMap<Symbol, NodeIndex>
+    {
+        &self.vars
+    }
+
+    /// Unbind a variable, later statements binding the same name
+    /// create a new variable node.
+    pub fn drop_var(&mut self, name: Symbol) -> Option<NodeIndex>
+    {
+        self.vars.remove(&name)
+    }
+
+    pub fn build_statement(&mut self, stmt: Statement, builder: &mut ASTBuilder) -> Result<&ExecutionPlan>
+    {
+        self.build(Program { statements: vec![stmt], ..Default::default() }, builder)
+    }
 
-        if let Some(name) = &stmt.variable {
-            let vx = self.add_var_node(name)?;
-            self.plan.try_add_edge(ix, vx, ExecEdge::default())?;
+    fn checkpoint(&self) -> Checkpoint
+    {
+        Checkpoint {
+            nodes: self.plan.node_count(),
+            edges: self.plan.edge_count(),
+            vars: self.vars.clone(),
+            tools: self.tools.clone(),
+            resources: self.resources.clone(),
+            defs: self.defs.clone(),
+        }
+    }
+
+    /// Remove everything added to the plan since the checkpoint. Edges
+    /// and nodes are removed last first so earlier indices are kept.
+    fn restore(&mut self, checkpoint: Checkpoint)
+    {
+        for ix in (checkpoint.edges..self.plan.edge_count()).rev() {
+            self.plan.remove_edge(EdgeIndex::new(ix));
+        }
+        for ix in (checkpoint.nodes..self.plan.node_count()).rev() {
+            self.plan.remove_node(NodeIndex::new(ix));
+        }
+
+        self.vars = checkpoint.vars;
+        self.tools = checkpoint.tools;
+        self.resources = checkpoint.resources;
+        self.defs = checkpoint.defs;
+    }
+
+    /// Add a definition, replacing any earlier one of the same name.
+    fn add_def(&mut self, def: Def) -> Result<()>
+    {
+        check_def(&def)?;
+        self.defs.insert(def.name, def);
+
+        Ok(())
+    }
+
+    fn add_statement(&mut self, stmt: &Statement, builder: &mut ASTBuilder) -> Result<()>
+    {
+        let end = self.build_flow(&stmt.flow, None, builder)?;
+
+        if let Some((name, span)) = &stmt.variable {
+            let vx = self.add_var_node(name, *span)?;
+            self.plan.try_add_edge(end.node, vx, ExecEdge::new(end.port))?;
         }
 
         if let Some(branches) = &stmt.branches {
             for branch in branches {
-                self.build_branch(branch, ix)?;
+                self.build_branch(branch, &end, builder)?;
             }
         }
 
-        Ok(&self.plan)
+        Ok(())
     }
 
+    /// Add a flow to the plan, its first item given `input` if piped
+    /// a result.
     fn build_flow(
         &mut self,
         flow: &Flow,
-        port: Symbol,
-        input: Option<NodeIndex>,
-    ) -> Result<NodeIndex>
+        input: Option<(Symbol, NodeIndex)>,
+        builder: &mut ASTBuilder,
+    ) -> Result<End>
     {
-        let mut current = input.map(|ix| (port, ix));
+        let mut current = input;
+        let mut outputs = vec![];
 
         for item in &flow.items {
+            outputs = vec![];
             current = match item {
+                FlowItem::Tool(tr) if let Some(def) = self.defs.get(&tr.name).cloned() => {
+                    let end = self.expand(&def, tr, current, builder)?;
+                    outputs = end.outputs;
+                    Some((end.port, end.node))
+                }
                 FlowItem::Tool(tr) => {
-                    let tool: Tool = tr.try_into()?;
+                    let tool = Tool::try_from(tr).at(tr.span)?;
 
                     let mut fr = vec![];
                     for f in tool.expand() {
-                        let ix = self.build_flow(&f.flow, syms().default, None)?;
-                        fr.push((f.port, ix));
+                        let end = self.build_flow(&f.flow, None, builder)?;
+                        fr.push((f.port, end.node));
                     }
 
-                    let ix = self.add_tool_node(&tr.id, tool)?;
+                    let ix = self.add_tool_node(tr, tool)?;
 
                     if let Some((p, src)) = current {
                         self.plan.try_add_edge(src, ix, ExecEdge::new(p))?;
@@ -78,10 +179,11 @@ impl Planner {
                     }
                     Some((syms().default, ix))
                 }
-                FlowItem::Variable(name) => {
+                FlowItem::Variable(name, span) => {
                     let ix = self.vars.get(name)
                         .cloned()
-                        .ok_or_else(|| anyhow!("undefined variable '{}'", resolve(*name)))?;
+                        .ok_or_else(|| anyhow!("undefined variable '{}'", resolve(*name)))
+                        .at(*span)?;
                     if let Some((p, src)) = current {
                         self.plan.try_add_edge(src, ix, ExecEdge::new(p))?;
                     }
@@ -90,21 +192,23 @@ impl Planner {
             }
         }
 
-        Ok(current.unwrap().1)
+        let (port, node) = current.unwrap();
+        Ok(End { port, node, outputs })
     }
 
-    fn build_branch(&mut self, branch: &Branch, input: NodeIndex) -> Result<()>
+    fn build_branch(&mut self, branch: &Branch, input: &End, builder: &mut ASTBuilder) -> Result<()>
     {
+        let (port, src) = input.output(branch)?;
         match &branch.target {
-            Target::Variable(name) => {
-                let ix = self.add_var_node(name)?;
-                self.plan.try_add_edge(input, ix, ExecEdge::new(branch.name))?;
+            Target::Variable(name, span) => {
+                let ix = self.add_var_node(name, *span)?;
+                self.plan.try_add_edge(src, ix, ExecEdge::new(port))?;
             }
             Target::Flow { flow, variable } => {
-                let ix = self.build_flow(flow, branch.name, Some(input))?;
-                if let Some(name) = variable {
-                    let vx = self.add_var_node(name)?;
-                    self.plan.try_add_edge(ix, vx, ExecEdge::default())?;
+                let end = self.build_flow(flow, Some((port, src)), builder)?;
+                if let Some((name, span)) = variable {
+                    let vx = self.add_var_node(name, *span)?;
+                    self.plan.try_add_edge(end.node, vx, ExecEdge::new(end.port))?;
                 }
             }
         }
@@ -112,25 +216,111 @@ impl Planner {
         Ok(())
     }
 
-    fn add_tool_node(&mut self, id: &ToolId, tool: Tool) -> Result<NodeIndex>
+    /// Add the flow of a definition where it's used, given its input
+    /// and arguments. Its branches to its outputs are where those come
+    /// from, the first being its result when it's piped on.
+    fn expand(&mut self, def: &Def, tr: &ToolRef, input: Option<(Symbol, NodeIndex)>, builder: &mut ASTBuilder) -> Result<End>
+    {
+        if self.expanding.iter().any(|(name, _)| *name == def.name) {
+            return Err(anyhow!("{} is used within its own definition", resolve(def.name))).at(tr.span)
+        }
+        let args = bind_args(def, tr).at(tr.span)?;
+        let (flow, branches) = instantiate(def, &args, builder)?;
+
+        self.expanding.push((def.name, tr.span));
+        let result = self.build_def_flow(def, &flow, branches.as_deref(), input, builder);
+        self.expanding.pop();
+
+        result.map_err(|e| with_notes(e, vec![use_note(def.name, tr.span)]))
+    }
+
+    fn build_def_flow(
+        &mut self,
+        def: &Def,
+        flow: &Flow,
+        branches: Option<&[Branch]>,
+        input: Option<(Symbol, NodeIndex)>,
+        builder: &mut ASTBuilder,
+    ) -> Result<End>
+    {
+        let end = self.build_flow(flow, input, builder)?;
+
+        let mut bound = HashMap::new();
+        for branch in branches.into_iter().flatten() {
+            let source = end.output(branch)?;
+            match &branch.target {
+                Target::Variable(output, _) => {
+                    bound.insert(*output, source);
+                }
+                Target::Flow { flow, variable } => {
+                    let branch_end = self.build_flow(flow, Some(source), builder)?;
+                    if let Some((output, _)) = variable {
+                        bound.insert(*output, (branch_end.port, branch_end.node));
+                    }
+                }
+            }
+        }
+
+        let outputs = def.outputs.iter()
+            .map(|output| (*output, bound[output]))
+            .collect::<Vec<_>>();
+        let (port, node) = outputs.first().map(|(_, source)| *source).unwrap_or((end.port, end.node));
+
+        Ok(End { port, node, outputs })
+    }
+
+    fn add_tool_node(&mut self, tr: &ToolRef, tool: Tool) -> Result<NodeIndex>
     {
-        let ix = if let Some(ix) = self.tools.get(id) {
+        let ix = if let Some(ix) = self.tools.get(&tr.id) {
             *ix
         } else {
-            let ix = self.plan.try_add_node(ExecNode::Tool(tool))?;
-            self.tools.insert(*id, ix);
+            let deps = self.resource_deps(&tool).at(tr.span)?;
+            let produces = tool.produces();
+
+            let ix = self.plan.try_add_node(ExecNode::Tool(Arc::new(tool), tr.span, self.expanding.iter().rev().copied().collect()))?;
+            self.tools.insert(tr.id, ix);
+            for src in deps {
+                self.plan.try_add_edge(src, ix, ExecEdge::order())?;
+            }
+            for res in produces {
+                self.resources.insert(res, ix);
+            }
             ix
         };
 
         Ok(ix)
     }
 
-    fn add_var_node(&mut self, name: &Symbol) -> Result<NodeIndex>
+    /// Nodes producing the resources a tool consumes. Tables must be
+    /// registered by an earlier statement, paths not written by the
+    /// plan must already exist.
+    fn resource_deps(&self, tool: &Tool) -> Result<Vec<NodeIndex>>
+    {
+        let mut deps = vec![];
+
+        for res in tool.consumes() {
+            match (self.resources.get(&res), res) {
+                (Some(ix), _) => deps.push(*ix),
+                (None, Resource::Table(name)) => {
+                    return Err(anyhow!("{} ({}) table '{name}' is not registered", tool.name(), tool.id()))
+                }
+                (None, Resource::Path(path)) => {
+                    if !path.exists() {
+                        return Err(anyhow!("input file not found: {}", path.display()))
+                    }
+                }
+            }
+        }
+
+        Ok(deps)
+    }
+
+    fn add_var_node(&mut self, name: &Symbol, span: Span) -> Result<NodeIndex>
     {
         let ix = if let Some(ix) = self.vars.get(name) {
             *ix
         } else {
-            let ix = self.plan.try_add_node(ExecNode::Variable(*name))?;
+            let ix = self.plan.try_add_node(ExecNode::Variable(*name, span))?;
             self.vars.insert(*name, ix);
             ix
         };
@@ -139,10 +329,55 @@ impl Planner {
     }
 }
 
-#[derive(Debug)]
+/// Where the result of a flow comes from, a port of its last node,
+/// and if it ends with a definition with outputs where each of those
+/// comes from.
+struct End {
+    port: Symbol,
+    node: NodeIndex,
+    outputs: Vec<(Symbol, (Symbol, NodeIndex))>,
+}
+
+impl End {
+    /// The port and node a branch takes its data from, an output of
+    /// the node or of the definition ending the flow.
+    fn output(&self, branch: &Branch) -> Result<(Symbol, NodeIndex)>
+    {
+        if self.outputs.is_empty() {
+            return Ok((branch.name, self.node))
+        }
+
+        let output = self.outputs.iter().find(|(name, _)| *name == branch.name);
+        let Some((_, source)) = output else {
+            let names = self.outputs.iter().map(|(name, _)| resolve(*name)).collect::<Vec<_>>();
+            return Err(anyhow!(
+                "no output '{}', the outputs are {}{}",
+                resolve(branch.name), names.join(", "), did_you_mean(resolve(branch.name), names.iter().copied())
+            )).at(branch.span)
+        };
+
+        Ok(*source)
+    }
+}
+
+/// Plan size and bindings before a build.
+#[derive(Clone)]
+struct Checkpoint {
+    nodes: usize,
+    edges: usize,
+    vars: HashMap<Symbol, NodeIndex>,
+    tools: HashMap<ToolId, NodeIndex>,
+    resources: HashMap<Resource, NodeIndex>,
+    defs: HashMap<Symbol, Def>,
+}
+
+/// A tool or variable of the plan and where it appears in the source.
+/// A tool expanded from a definition also has each use of a definition
+/// it came from and where it was, innermost first.
+#[derive(Clone, Debug)]
 pub enum ExecNode {
-    Tool(Tool),
-    Variable(Symbol),
+    Tool(Arc<Tool>, Span, Vec<(Symbol, Span)>),
+    Variable(Symbol, Span),
 }
 
 impl fmt::Display for ExecNode {
@@ -155,7 +390,7 @@ impl fmt::Display for ExecNode {
 impl ExecNode {
     pub fn is_source(&self) -> bool
     {
-        if let ExecNode::Tool(tool) = self {
+        if let ExecNode::Tool(tool, ..) = self {
             tool.is_source()
         } else {
             false
@@ -165,23 +400,80 @@ impl ExecNode {
     pub fn id(&self) -> ToolId
     {
         match self {
-            ExecNode::Tool(tool)    => tool.id(),
-            ExecNode::Variable(sym) => ToolId(sym.to_usize()),
+            ExecNode::Tool(tool, ..)   => tool.id(),
+            ExecNode::Variable(sym, _) => ToolId(sym.to_usize()),
         }
     }
 
     pub fn name(&self) -> &str
     {
        match self {
-            ExecNode::Tool(tool)    => tool.name(),
-            ExecNode::Variable(sym) => resolve(*sym),
+            ExecNode::Tool(tool, ..)   => tool.name(),
+            ExecNode::Variable(sym, _) => resolve(*sym),
+        }
+    }
+
+    pub fn span(&self) -> Span
+    {
+        match self {
+            ExecNode::Tool(_, span, _)  => *span,
+            ExecNode::Variable(_, span) => *span,
         }
     }
+
+    /// Where the definitions a tool was expanded from were used, to
+    /// report after its errors.
+    pub fn uses(&self) -> Vec<Diagnostic>
+    {
+        let ExecNode::Tool(_, _, uses) = self else { return vec![] };
+        uses.iter().map(|(name, span)| use_note(*name, *span)).collect()
+    }
+
+    /// An error of the node located at it, followed by where any
+    /// definitions it was expanded from were used.
+    pub fn locate(&self, error: anyhow::Error) -> anyhow::Error
+    {
+        with_notes(locate(error, self.span()), self.uses())
+    }
 }
 
-#[derive(Debug)]
+fn use_note(name: Symbol, span: Span) -> Diagnostic
+{
+    Diagnostic { message: format!("in {}, used here", resolve(name)), span }
+}
+
+/// A located error followed by `notes`, errors without a location are
+/// kept as they are.
+fn with_notes(error: anyhow::Error, notes: Vec<Diagnostic>) -> anyhow::Error
+{
+    if notes.is_empty() {
+        return error
+    }
+
+    let mut diagnostics = match error.downcast::<Diagnostics>() {
+        Ok(ds) => ds.0,
+        Err(error) => match error.downcast::<Diagnostic>() {
+            Ok(d) => vec![d],
+            Err(error) => return error,
+        },
+    };
+    diagnostics.extend(notes);
+
+    Diagnostics(diagnostics).into()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Dataframes flow from source to target on the edge's port
+    Data,
+    /// Target depends on a side effect of the source, no data flows
+    Order,
+}
+
+#[derive(Clone, Debug)]
 pub struct ExecEdge {
     pub port: Symbol,
+    pub kind: EdgeKind,
 }
 
 impl fmt::Display for ExecEdge {
@@ -194,13 +486,18 @@ impl fmt::Display for ExecEdge {
 impl ExecEdge {
     fn new(port: Symbol) -> Self
     {
-        ExecEdge { port }
+        ExecEdge { port, kind: EdgeKind::Data }
+    }
+
+    fn order() -> Self
+    {
+        ExecEdge { port: syms().default, kind: EdgeKind::Order }
     }
 }
 
 impl Default for ExecEdge {
     fn default() -> Self
     {
-        ExecEdge { port: syms().default }
+        ExecEdge::new(syms().default)
     }
 }
@@ -1,9 +1,21 @@
-use std::io::Write;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Result};
+use rustyline::Editor;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
 
-use anvil_parse::ASTBuilder;
-use crate::{run, run_stmt, Executor, Planner};
+use anvil_context::{intern, resolve};
+use anvil_parse::{render, ASTBuilder, Sources, StringScan};
+use crate::{run, tool_help, tools_help, Executor, Planner};
+use crate::completion::ReplHelper;
+
+const PROMPT: &str = "anvil> ";
+const CONTINUATION: &str = "  ...> ";
+const HISTORY_FILE: &str = ".anvil_history";
+/// Name given to statements typed at the prompt in error messages.
+const INPUT: &str = "<input>";
+const COMMANDS: &[&str] = &["drop", "exit", "help", "quit", "reset", "run", "show", "vars"];
 
 pub async fn run_repl(
     builder: &mut ASTBuilder,
@@ -11,46 +23,101 @@ pub async fn run_repl(
     executor: &mut Executor,
 ) -> Result<()>
 {
+    // Scripts imported during the session, which errors can be in
+    let mut sources = Sources::default();
+
+    let mut editor = Editor::<ReplHelper, DefaultHistory>::new()?;
+    editor.set_helper(Some(ReplHelper::new(COMMANDS)));
+    let history = history_path();
+    if let Some(path) = &history {
+        // No history file on first use
+        let _ = editor.load_history(path);
+    }
+
     loop {
-        let input = readline()?;
+        if let Some(helper) = editor.helper_mut() {
+            helper.update(builder, planner, executor);
+        }
+
+        let input = match read_input(&mut editor) {
+            Ok(input) => input,
+            Err(ReadlineError::Interrupted) => {
+                // Statements being continued are abandoned
+                if let Some(helper) = editor.helper_mut() {
+                    helper.set_pending("");
+                }
+                continue
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
         let line = input.trim();
         if line.is_empty() {
             continue;
         }
+        editor.add_history_entry(line)?;
 
         match parse_command(line) {
             Err(e)  => { println!("{e}"); continue; },
             Ok(cmd) => match cmd {
                 Some(Cmd::Run(script)) => {
-                    let source = std::fs::read_to_string(script)?;
-                    if let Err(e) = run(builder, planner, executor, &source).await {
-                        println!("{e}");
+                    let source = match std::fs::read_to_string(&script) {
+                        Ok(source) => source,
+                        Err(e)     => { println!("{script}: {e}"); continue; },
+                    };
+                    if let Err(e) = run(builder, planner, executor, &mut sources, &source, Path::new(&script)).await {
+                        println!("{}", render(&e, &script, &source, &sources));
                     }
                     continue;
                 }
                 Some(Cmd::Help(tool)) => {
-                    println!("<help for {tool}>");
+                    if tool.is_empty() {
+                        print_help();
+                    } else {
+                        match tool_help(&tool) {
+                            Ok(help) => println!("{help}"),
+                            Err(e) => println!("{e}"),
+                        }
+                    }
                     continue;
                 }
                 Some(Cmd::Reset) => {
+                    planner.reset();
                     executor.reset();
                     continue;
                 }
-                Some(Cmd::Exit)  => return Ok(()),
+                Some(Cmd::Vars) => {
+                    print_vars(planner, executor);
+                    continue;
+                }
+                Some(Cmd::Drop(name)) => {
+                    match planner.drop_var(intern(&name)) {
+                        Some(ix) => { executor.drop_values(ix); }
+                        None => println!("undefined variable '{name}'"),
+                    }
+                    continue;
+                }
+                Some(Cmd::Show(name, limit)) => {
+                    if let Err(e) = show_var(planner, executor, &name, limit).await {
+                        println!("{e}");
+                    }
+                    continue;
+                }
+                Some(Cmd::Exit)  => break,
                 None => {}
             }
         }
 
-        let stmt = if line.ends_with(';') {
-            line.to_string()
-        } else {
-            format!("{line};")
-        };
-        match run_stmt(builder, planner, executor, &stmt).await {
-            Ok(_)  => {}
-            Err(e) => println!("{e}"),
+        if let Err(e) = run(builder, planner, executor, &mut sources, line, Path::new(INPUT)).await {
+            println!("{}", render(&e, INPUT, line, &sources));
         }
     }
+
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+
+    Ok(())
 }
 
 enum Cmd {
@@ -58,17 +125,70 @@ enum Cmd {
     Help(String),
     Exit,
     Reset,
+    Vars,
+    Drop(String),
+    Show(String, Option<usize>),
+}
+
+/// Read a command or statements, prompting for more lines until the
+/// last statement is terminated.
+fn read_input(editor: &mut Editor<ReplHelper, DefaultHistory>) -> rustyline::Result<String>
+{
+    let mut input = editor.readline(PROMPT)?;
+
+    let cmd = input.split_whitespace().next().unwrap_or_default();
+    if COMMANDS.contains(&cmd) {
+        return Ok(input)
+    }
+
+    while !is_complete(&input) {
+        if let Some(helper) = editor.helper_mut() {
+            helper.set_pending(&input);
+        }
+        let line = editor.readline(CONTINUATION)?;
+        input.push('\n');
+        input.push_str(&line);
+    }
+
+    if let Some(helper) = editor.helper_mut() {
+        helper.set_pending("");
+    }
+
+    Ok(input)
 }
 
-fn readline() -> Result<String>
+/// Input is complete once it ends with a ';' outside of any string,
+/// comment, tool brackets or parenthesized flow.
+fn is_complete(input: &str) -> bool
 {
-    write!(std::io::stdout(), "anvil> ")?;
-    std::io::stdout().flush()?;
+    let mut depth = 0;
+    let mut complete = true;
+    let mut strings = StringScan::default();
+    let mut in_comment = false;
 
-    let mut buffer = String::new();
-    std::io::stdin().read_line(&mut buffer)?;
+    for (i, c) in input.char_indices() {
+        if in_comment {
+            in_comment = c != '\n';
+        } else if strings.step(&input[i..]) {
+            complete = false;
+        } else {
+            match c {
+                '#'  => in_comment = true,
+                '[' | '(' => { depth += 1; complete = false; }
+                ']' | ')' => { depth -= 1; complete = false; }
+                ';' if depth <= 0 => complete = true,
+                c if c.is_whitespace() => {}
+                _ => complete = false,
+            }
+        }
+    }
 
-    Ok(buffer)
+    complete && !strings.in_string()
+}
+
+fn history_path() -> Option<PathBuf>
+{
+    std::env::home_dir().map(|home| home.join(HISTORY_FILE))
 }
 
 fn parse_command(line: &str) -> Result<Option<Cmd>>
@@ -84,15 +204,120 @@ fn parse_command(line: &str) -> Result<Option<Cmd>>
         }
         Some("help") => {
             if let Some(tool) = iter.next() {
-                Some(Cmd::Help(tool.to_string()))
+                Some(Cmd::Help(tool.trim().to_string()))
             } else {
                 Some(Cmd::Help("".into()))
             }
         }
         Some("reset") =>  Some(Cmd::Reset),
+        Some("vars")  =>  Some(Cmd::Vars),
+        Some("drop")  => {
+            if let Some(var) = iter.next() {
+                Some(Cmd::Drop(var.trim().to_string()))
+            } else {
+                return Err(anyhow!("drop command requires a variable name"))
+            }
+        }
+        Some("show") => {
+            let mut args = iter.next().unwrap_or_default().split_whitespace();
+            let var = args.next()
+                .ok_or_else(|| anyhow!("show command requires a variable name"))?;
+            let limit = args.next()
+                .map(|n| n.parse::<usize>())
+                .transpose()
+                .map_err(|_| anyhow!("show command row limit must be a number"))?;
+            Some(Cmd::Show(var.to_string(), limit))
+        }
         Some("exit") | Some("quit") => Some(Cmd::Exit),
         _ => None,
     };
 
     Ok(cmd)
-}
\ No newline at end of file
+}
+
+fn print_help()
+{
+    println!("commands:");
+    println!("  run <script>        run the statements in a script file");
+    println!("  help [tool]         list the tools or describe one");
+    println!("  vars                list variables and their schemas");
+    println!("  show <var> [rows]   print the value of a variable");
+    println!("  drop <var>          remove a variable");
+    println!("  reset               clear all variables and tables");
+    println!("  exit, quit          leave the REPL");
+    println!();
+    println!("tools:");
+    println!("{}", tools_help());
+}
+
+fn print_vars(planner: &Planner, executor: &Executor)
+{
+    let mut vars = planner.vars().iter()
+        .map(|(name, ix)| (resolve(*name), *ix))
+        .collect::<Vec<_>>();
+    vars.sort();
+
+    for (name, ix) in vars {
+        match executor.values(ix).and_then(|v| v.get_one()) {
+            Some(df) => {
+                let fields = df.schema().fields().iter()
+                    .map(|f| format!("{}: {}", f.name(), f.data_type()))
+                    .collect::<Vec<_>>();
+                println!("{name} ({})", fields.join(", "));
+            }
+            None => println!("{name} <not set>"),
+        }
+    }
+}
+
+async fn show_var(planner: &Planner, executor: &Executor, name: &str, limit: Option<usize>) -> Result<()>
+{
+    let ix = planner.vars().get(&intern(name))
+        .ok_or_else(|| anyhow!("undefined variable '{name}'"))?;
+    let df = executor.values(*ix)
+        .and_then(|v| v.get_one())
+        .cloned()
+        .ok_or_else(|| anyhow!("variable '{name}' has no value"))?;
+
+    if let Some(limit) = limit {
+        df.show_limit(limit).await?;
+    } else {
+        df.show().await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complete_statements()
+    {
+        assert!(is_complete(""));
+        assert!(is_complete("[input: 'a.csv'] | [print];"));
+        assert!(is_complete("a | [print]; b | [print];  "));
+        assert!(is_complete("a | [print]; # done"));
+        assert!(is_complete("[input: 'a.csv']\n  | [print];\n"));
+    }
+
+    #[test]
+    fn incomplete_statements()
+    {
+        assert!(!is_complete("[input: 'a.csv']"));
+        assert!(!is_complete("[input: 'a.csv'] | [print]; b"));
+        assert!(!is_complete("[input: 'a.csv'] # ends here;"));
+    }
+
+    #[test]
+    fn semicolons_inside_are_ignored()
+    {
+        assert!(!is_complete("[sql: 'SELECT 1;'"));
+        assert!(!is_complete("[sql: 'SELECT 1;"));
+        assert!(!is_complete("[sql: '''SELECT 1;\n"));
+        assert!(!is_complete("[join: (a | [print];"));
+        assert!(is_complete("[sql: 'SELECT \\'a;\\''];"));
+        assert!(is_complete("[sql: '''SELECT 1;\n'''];"));
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::{anyhow, Result};
+use datafusion::prelude::Expr;
+
+use anvil_context::resolve;
+use crate::eval_expression;
+use crate::tools::{parse_expression, parse_expressions, ArgSpec, ArgType, ArgValue, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "aggregate",
+    description: "Group rows and compute aggregate expressions over each group",
+    positional: &[],
+    keyword: &[
+        ArgSpec { name: "by", ty: ArgType::Expression, required: false, default: None, values: &[], description: "Comma separated grouping expressions" },
+    ],
+    named: Some(ArgSpec { name: "<column>", ty: ArgType::Expression, required: false, default: None, values: &[], description: "Aggregate expression for a new column" }),
+    inputs: &["*"],
+    outputs: &["*"],
+};
+
+pub async fn run(id: &ToolId, args: &AggregateArgs, inputs: Values) -> Result<Values>
+{
+    let df = inputs.get_one().cloned()
+        .ok_or_else(|| anyhow!("aggregate tool ({id}) requires input"))?;
+    let df = df.aggregate(args.group.clone(), args.aggr.clone())?;
+
+    Ok(Values::new(df))
+}
+
+#[derive(Debug)]
+pub struct AggregateArgs {
+    group: Vec<Expr>,
+    aggr: Vec<Expr>,
+}
+
+impl TryFrom<&ToolRef> for AggregateArgs {
+    type Error = anyhow::Error;
+
+    fn try_from(tr: &ToolRef) -> Result<Self>
+    {
+        let args = ToolArgs::new(tr, &SPEC)?;
+
+        let mut group = Vec::new();
+        if let Some(by) = args.optional_string("by")? {
+            for expr in parse_expressions(&by)? {
+                group.push(eval_expression(&expr)?);
+            }
+        }
+
+        let mut aggr = Vec::new();
+        for (ident, value) in args.named() {
+            let ArgValue::String(s) = value else {
+                return Err(anyhow!("aggregate tool expression must be a string {value:?}"))
+            };
+            let expr = parse_expression(s)?;
+            let expr = eval_expression(&expr)?;
+            aggr.push(expr.alias(resolve(ident)));
+        }
+
+        if aggr.is_empty() {
+            return Err(anyhow!("aggregate tool requires at least one aggregate expression"))
+        }
+
+        Ok(AggregateArgs { group, aggr })
+    }
+}
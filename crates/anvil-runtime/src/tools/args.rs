@@ -1,125 +1,176 @@
-use std::collections::HashMap;
-
 use anyhow::{anyhow, Result};
 
 use anvil_context::{intern, resolve, Symbol};
 use crate::tools::*;
+use crate::tools::did_you_mean;
 
 
+/// A tool's arguments checked against its spec. Positional arguments
+/// are looked up by the names the spec gives them and omitted ones
+/// take the spec's default.
 #[derive(Debug)]
 pub struct ToolArgs {
+    spec: &'static ToolSpec,
     positional: Vec<ArgValue>,
-    keyword: HashMap<Symbol, ArgValue>,
+    keyword: Vec<(Symbol, ArgValue)>,
+    /// Location of the tool, given to flows named by a string.
+    span: Span,
 }
 
 impl ToolArgs {
-    pub fn new(args: &[ToolArg]) -> Result<Self>
+    /// Collect the arguments, checking their names, types and count
+    /// against the tool's spec.
+    pub fn new(tr: &ToolRef, spec: &'static ToolSpec) -> Result<Self>
     {
-        let mut keyword = HashMap::new();
+        let mut keyword: Vec<(Symbol, ArgValue)> = Vec::new();
         let mut positional = Vec::new();
 
-        for arg in args {
+        for arg in &tr.args {
             match arg {
-                ToolArg::Keyword { ident, value } => {
-                    if keyword.insert(*ident, value.clone()).is_some() {
+                ToolArg::Keyword { ident, value, .. } => {
+                    if keyword.iter().any(|(key, _)| key == ident) {
                         return Err(anyhow!("duplicate named argument '{}'", resolve(*ident)));
                     }
+                    keyword.push((*ident, value.clone()));
                 }
-                ToolArg::Positional(v) => positional.push(v.clone()),
+                ToolArg::Positional(v, _) => positional.push(v.clone()),
             }
         }
 
-        Ok(Self { positional, keyword })
-    }
+        let name = spec.name;
+        if positional.len() > spec.positional.len() {
+            return match spec.positional.len() {
+                0 => Err(anyhow!("{name} tool does not take positional arguments")),
+                n => Err(anyhow!("{name} tool takes at most {n} positional argument(s)")),
+            }
+        }
+        for (arg, value) in spec.positional.iter().zip(&positional) {
+            arg.check(name, value)?;
+        }
+        if let Some(arg) = spec.positional.iter().skip(positional.len()).find(|arg| arg.required) {
+            return Err(anyhow!("{name} tool missing required positional argument '{}'", arg.name))
+        }
 
-    pub fn required_positional_string(&self, index: usize, name: &str) -> Result<String>
-    {
-        match self.positional.get(index) {
-            Some(ArgValue::String(s)) => Ok(s.clone()),
-            Some(_) => Err(anyhow!("'{name}' must be a string")),
-            None => Err(anyhow!("missing required positional argument '{name}'")),
+        for (key, value) in &keyword {
+            let key = resolve(*key);
+            match spec.keyword(key) {
+                Some(arg) => arg.check(name, value)?,
+                None => {
+                    let names = spec.keyword.iter().map(|arg| arg.name);
+                    return Err(anyhow!(
+                        "unexpected named argument '{key}' for {name} tool{}", did_you_mean(key, names)
+                    ))
+                }
+            }
+        }
+        let missing = spec.keyword.iter()
+            .find(|arg| arg.required && !keyword.iter().any(|(key, _)| resolve(*key) == arg.name));
+        if let Some(arg) = missing {
+            return Err(anyhow!("{name} tool missing required argument '{}'", arg.name))
         }
+
+        Ok(Self { spec, positional, keyword, span: tr.span })
     }
 
-    pub fn required_positional_integer(&self, index: usize, name: &str) -> Result<i64>
+    /// Value of an argument, or its default if omitted.
+    pub fn value(&self, name: &str) -> Option<ArgValue>
     {
-        match self.positional.get(index) {
-            Some(ArgValue::Integer(n)) => Ok(*n),
-            Some(_) => Err(anyhow!("'{name}' must be a integer")),
-            None => Err(anyhow!("missing required positional argument '{name}'")),
-        }
+        let given = match self.spec.positional.iter().position(|arg| arg.name == name) {
+            Some(index) => self.positional.get(index).cloned(),
+            None => {
+                let key = intern(name);
+                self.keyword.iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, value)| value.clone())
+            }
+        };
+
+        given.or_else(|| self.spec.arg(name).and_then(|arg| arg.default).map(ArgValue::from))
     }
 
-    pub fn required_positional_flow(&self, index: usize, name: &str) -> Result<Flow>
+    pub fn string(&self, name: &str) -> Result<String>
     {
-        match self.positional.get(index) {
-            Some(av) => {
-                match av {
-                    ArgValue::Flow(f)   => Ok(f.clone()),
-                    ArgValue::Ident(s)  => Ok(Flow { items: vec![FlowItem::Variable(intern(s))]}),
-                    ArgValue::String(s) => Ok(Flow { items: vec![FlowItem::Variable(intern(s))]}),
-                    _ => Err(anyhow!("'{name}' must be flow, identifier or string"))
-                }
-            }
-            None => Err(anyhow!("missing required positional argument '{name}'")),
-        }
+        self.optional_string(name)?
+            .ok_or_else(|| anyhow!("{} tool missing argument '{name}'", self.spec.name))
     }
 
-    pub fn optional_positional_string(&self, index: usize, name: &str) -> Result<Option<String>>
+    pub fn optional_string(&self, name: &str) -> Result<Option<String>>
     {
-        match self.positional.get(index) {
-            Some(ArgValue::String(s)) => Ok(Some(s.clone())),
-            Some(_) => Err(anyhow!("'{name}' must be a string")),
+        match self.value(name) {
+            Some(ArgValue::String(s)) => Ok(Some(s)),
+            Some(_) => Err(anyhow!("{} tool '{name}' must be a string", self.spec.name)),
             None => Ok(None),
         }
     }
 
-    pub fn optional_positional_integer(&self, index: usize, name: &str) -> Result<Option<i64>>
+    pub fn strings(&self, name: &str) -> Result<Vec<String>>
     {
-        match self.positional.get(index) {
-            Some(ArgValue::Integer(n)) => Ok(Some(*n)),
-            Some(_) => Err(anyhow!("'{name}' must be a integer")),
-            None => Ok(None),
-        }
+        self.optional_strings(name)?
+            .ok_or_else(|| anyhow!("{} tool missing argument '{name}'", self.spec.name))
     }
 
-    pub fn optional_string(&self, key: Symbol) -> Result<Option<String>>
+    /// A list of strings. A string is split on its commas, the way
+    /// lists were written before there were list literals.
+    pub fn optional_strings(&self, name: &str) -> Result<Option<Vec<String>>>
     {
-        match self.keyword.get(&key) {
-            Some(ArgValue::String(s)) => Ok(Some(s.clone())),
-            Some(_) => Err(anyhow!("{} must be a string", resolve(key))),
-            None => Ok(None),
-        }
+        let strings = match self.value(name) {
+            Some(ArgValue::String(s)) => s.split(',').map(|s| s.to_string()).collect(),
+            Some(ArgValue::List(values)) => {
+                values.into_iter()
+                    .map(|value| match value {
+                        ArgValue::String(s) => Ok(s),
+                        _ => Err(anyhow!("{} tool '{name}' must be a list of strings", self.spec.name)),
+                    })
+                    .collect::<Result<_>>()?
+            }
+            Some(_) => return Err(anyhow!("{} tool '{name}' must be a list of strings", self.spec.name)),
+            None => return Ok(None),
+        };
+
+        Ok(Some(strings))
     }
 
-    pub fn optional_integer(&self, key: Symbol) -> Result<Option<i64>>
+    pub fn integer(&self, name: &str) -> Result<i64>
     {
-        match self.keyword.get(&key) {
-            Some(ArgValue::Integer(n)) => Ok(Some(*n)),
-            Some(_) => Err(anyhow!("{} must be an integer", resolve(key))),
-            None => Ok(None),
-        }
+        self.optional_integer(name)?
+            .ok_or_else(|| anyhow!("{} tool missing argument '{name}'", self.spec.name))
     }
 
-    pub fn optional_bool(&self, key: Symbol) -> Result<Option<bool>>
+    pub fn optional_integer(&self, name: &str) -> Result<Option<i64>>
     {
-        match self.keyword.get(&key) {
-            Some(ArgValue::Boolean(b)) => Ok(Some(*b)),
-            Some(_) => Err(anyhow!("{} must be a boolean", resolve(key))),
+        match self.value(name) {
+            Some(ArgValue::Integer(n)) => Ok(Some(n)),
+            Some(_) => Err(anyhow!("{} tool '{name}' must be an integer", self.spec.name)),
             None => Ok(None),
         }
     }
 
-    pub fn check_named_args(&self, allowed: &[Symbol]) -> Result<()>
+    pub fn boolean(&self, name: &str) -> Result<bool>
     {
-        for key in self.keyword.keys() {
-            if !allowed.contains(key) {
-                return Err(anyhow!("unexpected named argument '{}'", resolve(*key)));
-            }
+        match self.value(name) {
+            Some(ArgValue::Boolean(b)) => Ok(b),
+            Some(_) => Err(anyhow!("{} tool '{name}' must be a boolean", self.spec.name)),
+            None => Err(anyhow!("{} tool missing argument '{name}'", self.spec.name)),
         }
+    }
 
-        Ok(())
+    pub fn flow(&self, name: &str) -> Result<Flow>
+    {
+        match self.value(name) {
+            Some(ArgValue::Flow(f))        => Ok(f),
+            Some(ArgValue::Ident(s, span)) => Ok(Flow::variable(intern(&s), span)),
+            Some(ArgValue::String(s))      => Ok(Flow::variable(intern(&s), self.span)),
+            Some(_) => Err(anyhow!("{} tool '{name}' must be flow, identifier or string", self.spec.name)),
+            None => Err(anyhow!("{} tool missing argument '{name}'", self.spec.name)),
+        }
     }
 
+    /// Keyword arguments not declared by the spec, e.g. the new
+    /// columns of a projection, in the order they were written.
+    pub fn named(&self) -> impl Iterator<Item = (Symbol, &ArgValue)>
+    {
+        self.keyword.iter()
+            .filter(|(key, _)| !self.spec.keyword.iter().any(|arg| arg.name == resolve(*key)))
+            .map(|(key, value)| (*key, value))
+    }
 }
-
@@ -0,0 +1,23 @@
+use anyhow::{anyhow, Result};
+
+use crate::tools::{ToolId, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "cache",
+    description: "Materialize the input in memory so downstream tools don't recompute it",
+    positional: &[],
+    keyword: &[],
+    named: None,
+    inputs: &["*"],
+    outputs: &["*"],
+};
+
+pub async fn run(id: &ToolId, inputs: Values) -> Result<Values>
+{
+    let df = inputs.get_one().cloned()
+        .ok_or_else(|| anyhow!("cache tool ({id}) requires input"))?;
+
+    let df = df.cache().await?;
+
+    Ok(Values::new(df))
+}
@@ -2,8 +2,19 @@ use anyhow::{anyhow, Result};
 use datafusion::prelude::*;
 use datafusion::execution::context::SessionContext;
 
-use anvil_context::intern;
-use crate::tools::{ToolArgs, ToolId, ToolRef, Values};
+use crate::tools::{ArgSpec, ArgType, Literal, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "count",
+    description: "Count the input rows",
+    positional: &[
+        ArgSpec { name: "col", ty: ArgType::String, required: false, default: Some(Literal::String("count")), values: &[], description: "Name of the count column" },
+    ],
+    keyword: &[],
+    named: None,
+    inputs: &["*"],
+    outputs: &["*"],
+};
 
 pub async fn run(id: &ToolId, args: &CountArgs, inputs: Values, ctx: &SessionContext) -> Result<Values>
 {
@@ -17,6 +28,15 @@ pub async fn run(id: &ToolId, args: &CountArgs, inputs: Values, ctx: &SessionCon
     Ok(Values::new(df))
 }
 
+/// Output of the tool, with a placeholder count.
+pub fn infer(args: &CountArgs, ctx: &SessionContext) -> Result<Values>
+{
+    let df = ctx.read_empty()?
+        .with_column(&args.col, lit(0_i64))?;
+
+    Ok(Values::new(df))
+}
+
 #[derive(Debug)]
 pub struct CountArgs {
     col: String,
@@ -27,10 +47,9 @@ impl TryFrom<&ToolRef> for CountArgs {
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
-        let args = ToolArgs::new(&tr.args)?;
-        args.check_named_args(&[intern("col")])?;
+        let args = ToolArgs::new(tr, &SPEC)?;
 
-        let col = args.optional_positional_string(0, "col")?.unwrap_or("count".into());
+        let col = args.string("col")?;
 
         Ok(CountArgs { col })
     }
@@ -1,6 +1,16 @@
 use anyhow::{anyhow, Result};
 
-use crate::tools::{ToolId, Values};
+use crate::tools::{ToolId, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "distinct",
+    description: "Remove duplicate rows",
+    positional: &[],
+    keyword: &[],
+    named: None,
+    inputs: &["*"],
+    outputs: &["*"],
+};
 
 pub async fn run(id: &ToolId, inputs: Values) -> Result<Values>
 {
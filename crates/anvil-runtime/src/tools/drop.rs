@@ -1,13 +1,25 @@
 use anyhow::{anyhow, Result};
 
-use crate::tools::{ToolArgs, ToolId, ToolRef, Values};
+use crate::tools::{ArgSpec, ArgType, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "drop",
+    description: "Remove columns",
+    positional: &[
+        ArgSpec { name: "cols", ty: ArgType::List, required: true, default: None, values: &[], description: "Column names" },
+    ],
+    keyword: &[],
+    named: None,
+    inputs: &["*"],
+    outputs: &["*"],
+};
 
 pub async fn run(id: &ToolId, args: &DropArgs, inputs: Values) -> Result<Values>
 {
     let df = inputs.get_one().cloned()
         .ok_or_else(|| anyhow!("drop tool ({id}) requires input"))?;
 
-    let cols = args.cols.split(',').collect::<Vec<_>>();
+    let cols = args.cols.iter().map(|c| c.as_str()).collect::<Vec<_>>();
     let df = df.drop_columns(&cols)?;
 
     Ok(Values::new(df))
@@ -15,7 +27,7 @@ pub async fn run(id: &ToolId, args: &DropArgs, inputs: Values) -> Result<Values>
 
 #[derive(Debug)]
 pub struct DropArgs {
-    cols: String,
+    cols: Vec<String>,
 }
 
 impl TryFrom<&ToolRef> for DropArgs {
@@ -23,10 +35,9 @@ impl TryFrom<&ToolRef> for DropArgs {
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
-        let args = ToolArgs::new(&tr.args)?;
-        args.check_named_args(&[])?;
+        let args = ToolArgs::new(tr, &SPEC)?;
 
-        let cols = args.required_positional_string(0, "cols")?;
+        let cols = args.strings("cols")?;
 
         Ok(DropArgs { cols })
     }
@@ -1,25 +1,38 @@
 use anyhow::{anyhow, Result};
 use datafusion::scalar::ScalarValue;
 
-use crate::tools::{ToolArgs, ToolId, ToolRef, Values};
+use crate::tools::{ArgSpec, ArgType, ArgValue, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "fill",
+    description: "Replace null values",
+    positional: &[
+        ArgSpec { name: "value", ty: ArgType::Any, required: true, default: None, values: &[], description: "Value replacing nulls, or a map of column to value" },
+        ArgSpec { name: "cols", ty: ArgType::List, required: false, default: None, values: &[], description: "Column names, all columns if omitted" },
+    ],
+    keyword: &[],
+    named: None,
+    inputs: &["*"],
+    outputs: &["*"],
+};
 
 pub async fn run(id: &ToolId, args: &FillArgs, inputs: Values) -> Result<Values>
 {
-    let df = inputs.get_one().cloned()
+    let mut df = inputs.get_one().cloned()
         .ok_or_else(|| anyhow!("fill tool ({id}) requires input"))?;
 
-    let cols = args.cols.as_ref()
-        .map(|s| s.split(',').map(|c| c.to_owned()).collect())
-        .unwrap_or_default();
-    let df = df.fill_null(ScalarValue::from(args.value), cols)?;
+    // Columns of another type than the value are left as they are
+    for (value, cols) in &args.fills {
+        df = df.fill_null(value.clone(), cols.clone())?;
+    }
 
     Ok(Values::new(df))
 }
 
 #[derive(Debug)]
 pub struct FillArgs {
-    value: i64,
-    cols: Option<String>,
+    /// Values and the columns they fill, all columns if none
+    fills: Vec<(ScalarValue, Vec<String>)>,
 }
 
 impl TryFrom<&ToolRef> for FillArgs {
@@ -27,12 +40,35 @@ impl TryFrom<&ToolRef> for FillArgs {
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
-        let args = ToolArgs::new(&tr.args)?;
-        args.check_named_args(&[])?;
+        let args = ToolArgs::new(tr, &SPEC)?;
 
-        let value = args.required_positional_integer(0, "value")?;
-        let cols  = args.optional_positional_string(1, "cols")?;
+        let cols = args.optional_strings("cols")?;
+        let fills = match args.value("value") {
+            Some(ArgValue::Map(entries)) => {
+                if cols.is_some() {
+                    return Err(anyhow!("fill tool 'cols' can't be given with a map of values"))
+                }
+                entries.into_iter()
+                    .map(|(col, value)| Ok((scalar(&value)?, vec![col])))
+                    .collect::<Result<_>>()?
+            }
+            Some(value) => vec![(scalar(&value)?, cols.unwrap_or_default())],
+            None => return Err(anyhow!("fill tool missing argument 'value'")),
+        };
 
-        Ok(FillArgs { value, cols })
+        Ok(FillArgs { fills })
     }
-}
\ No newline at end of file
+}
+
+fn scalar(value: &ArgValue) -> Result<ScalarValue>
+{
+    let scalar = match value {
+        ArgValue::Boolean(b) => ScalarValue::from(*b),
+        ArgValue::Integer(n) => ScalarValue::from(*n),
+        ArgValue::Float(n)   => ScalarValue::from(*n),
+        ArgValue::String(s)  => ScalarValue::from(s.as_str()),
+        _ => return Err(anyhow!("fill tool value must be a number, string or boolean")),
+    };
+
+    Ok(scalar)
+}
@@ -2,7 +2,19 @@ use anyhow::{anyhow, Result};
 
 use anvil_context::syms;
 use crate::eval_expression;
-use crate::tools::{parse_expression, ToolArgs, ToolId, ToolRef, Values};
+use crate::tools::{parse_expression, ArgSpec, ArgType, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "filter",
+    description: "Split rows on a predicate",
+    positional: &[
+        ArgSpec { name: "predicate", ty: ArgType::Expression, required: true, default: None, values: &[], description: "Boolean expression" },
+    ],
+    keyword: &[],
+    named: None,
+    inputs: &["*"],
+    outputs: &["true", "false"],
+};
 
 pub async fn run(id: &ToolId, args: &FilterArgs, inputs: Values) -> Result<Values>
 {
@@ -33,10 +45,9 @@ impl TryFrom<&ToolRef> for FilterArgs {
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
-        let args = ToolArgs::new(&tr.args)?;
-        args.check_named_args(&[])?;
+        let args = ToolArgs::new(tr, &SPEC)?;
 
-        let predicate = args.required_positional_string(0, "path")?;
+        let predicate = args.string("predicate")?;
 
         Ok(FilterArgs { predicate })
     }
@@ -1,12 +1,25 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Result};
 use datafusion::execution::context::SessionContext;
 use datafusion::execution::options::ArrowReadOptions;
 use datafusion::prelude::{AvroReadOptions, CsvReadOptions, NdJsonReadOptions, ParquetReadOptions};
 
-use anvil_context::intern;
-use crate::tools::{ToolArgs, ToolId, ToolRef, Values};
+use crate::tools::{ArgSpec, ArgType, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "input",
+    description: "Read a file",
+    positional: &[
+        ArgSpec { name: "path", ty: ArgType::String, required: true, default: None, values: &[], description: "File or directory to read" },
+    ],
+    keyword: &[
+        ArgSpec { name: "format", ty: ArgType::String, required: false, default: None, values: &["csv", "avro", "json", "arrow", "parquet"], description: "File format, from the extension if omitted" },
+    ],
+    named: None,
+    inputs: &[],
+    outputs: &["*"],
+};
 
 pub async fn run(_id: &ToolId, args: &InputArgs, ctx: &SessionContext) -> Result<Values>
 {
@@ -23,6 +36,11 @@ pub async fn run(_id: &ToolId, args: &InputArgs, ctx: &SessionContext) -> Result
     Ok(Values::new(df))
 }
 
+pub fn path(args: &InputArgs) -> PathBuf
+{
+    PathBuf::from(&args.path)
+}
+
 #[derive(Debug)]
 #[allow(non_camel_case_types)]
 enum InputFormat {
@@ -44,16 +62,12 @@ impl TryFrom<&ToolRef> for InputArgs {
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
-        let args = ToolArgs::new(&tr.args)?;
-        args.check_named_args(&[intern("format")])?;
+        let args = ToolArgs::new(tr, &SPEC)?;
 
-        let path = args.required_positional_string(0, "input: path")?;
+        let path = args.string("path")?;
         let fpath = Path::new(&path);
-        if !fpath.exists() {
-            return Err(anyhow!("input file not found: {}", fpath.display()));
-        }
 
-        let format = args.optional_string(intern("format"))?;
+        let format = args.optional_string("format")?;
         let format = match format {
             Some(s) => {
                 match s.as_str() {
@@ -2,7 +2,24 @@ use anyhow::{anyhow, Result};
 use datafusion::prelude::JoinType;
 
 use anvil_context::syms;
-use crate::tools::{Flow, FlowRef, ToolArgs, ToolId, ToolRef, Values};
+use crate::tools::{ArgSpec, ArgType, Flow, FlowRef, Literal, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "join",
+    description: "Join two flows on matching columns",
+    positional: &[
+        ArgSpec { name: "left", ty: ArgType::Flow, required: true, default: None, values: &[], description: "Left flow" },
+        ArgSpec { name: "right", ty: ArgType::Flow, required: true, default: None, values: &[], description: "Right flow" },
+    ],
+    keyword: &[
+        ArgSpec { name: "cols_lt", ty: ArgType::List, required: true, default: None, values: &[], description: "Left join columns" },
+        ArgSpec { name: "cols_rt", ty: ArgType::List, required: true, default: None, values: &[], description: "Right join columns" },
+        ArgSpec { name: "type", ty: ArgType::String, required: false, default: Some(Literal::String("inner")), values: &["inner", "outer", "left", "right"], description: "Join type" },
+    ],
+    named: None,
+    inputs: &["left", "right"],
+    outputs: &["*"],
+};
 
 pub async fn run(id: &ToolId, args: &JoinArgs, inputs: Values) -> Result<Values>
 {
@@ -11,8 +28,8 @@ pub async fn run(id: &ToolId, args: &JoinArgs, inputs: Values) -> Result<Values>
     let df_rt = inputs.dfs.get(&syms().right).cloned()
         .ok_or_else(|| anyhow!("join tool ({id}) requires right port"))?;
 
-    let cols_lt = args.cols_lt.split(',').collect::<Vec<_>>();
-    let cols_rt = args.cols_rt.split(',').collect::<Vec<_>>();
+    let cols_lt = args.cols_lt.iter().map(|c| c.as_str()).collect::<Vec<_>>();
+    let cols_rt = args.cols_rt.iter().map(|c| c.as_str()).collect::<Vec<_>>();
     let df = df_lt.join(df_rt, args.join_type, &cols_lt, &cols_rt, None)?;
 
     Ok(Values::new(df))
@@ -28,8 +45,8 @@ pub fn flows(args: &JoinArgs) -> Vec<FlowRef>
 
 #[derive(Debug)]
 pub struct JoinArgs {
-    cols_lt: String,
-    cols_rt: String,
+    cols_lt: Vec<String>,
+    cols_rt: Vec<String>,
     flow_lt: Flow,
     flow_rt: Flow,
     join_type: JoinType,
@@ -40,21 +57,15 @@ impl TryFrom<&ToolRef> for JoinArgs {
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
-        let args = ToolArgs::new(&tr.args)?;
-        args.check_named_args(&[syms().join_type, syms().cols_lt, syms().cols_rt])?;
+        let args = ToolArgs::new(tr, &SPEC)?;
 
-        let flow_lt = args.required_positional_flow(0, "left")?;
-        let flow_rt = args.required_positional_flow(1, "right")?;
+        let flow_lt = args.flow("left")?;
+        let flow_rt = args.flow("right")?;
 
-        let cols_lt = args.optional_string(syms().cols_lt)?.ok_or_else(
-            || anyhow!("join 'cols_lt' argument does not exist")
-        )?;
-        let cols_rt = args.optional_string(syms().cols_rt)?.ok_or_else(
-            || anyhow!("join 'cols_tr' argument does not exist")
-        )?;
+        let cols_lt = args.strings("cols_lt")?;
+        let cols_rt = args.strings("cols_rt")?;
 
-        let join_type = args.optional_string(syms().join_type)?;
-        let join_type = join_type.unwrap_or("inner".into());
+        let join_type = args.string("type")?;
         let join_type = match join_type.as_str() {
             "inner" => JoinType::Inner,
             "outer" => JoinType::Full,
@@ -63,7 +74,6 @@ impl TryFrom<&ToolRef> for JoinArgs {
             _ => return Err(anyhow!("uknown join type '{join_type}")),
         };
 
-
         Ok(JoinArgs {
             cols_lt,
             cols_rt,
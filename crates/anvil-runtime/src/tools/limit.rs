@@ -1,7 +1,20 @@
 use anyhow::{anyhow, Result};
 
-use anvil_context::intern;
-use crate::tools::{ToolArgs, ToolId, ToolRef, Values};
+use crate::tools::{ArgSpec, ArgType, Literal, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "limit",
+    description: "Keep a number of rows",
+    positional: &[
+        ArgSpec { name: "count", ty: ArgType::Integer, required: true, default: None, values: &[], description: "Number of rows to keep" },
+    ],
+    keyword: &[
+        ArgSpec { name: "skip", ty: ArgType::Integer, required: false, default: Some(Literal::Integer(0)), values: &[], description: "Number of rows to skip first" },
+    ],
+    named: None,
+    inputs: &["*"],
+    outputs: &["*"],
+};
 
 pub async fn run(id: &ToolId, args: &LimitArgs, inputs: Values) -> Result<Values>
 {
@@ -23,11 +36,10 @@ impl TryFrom<&ToolRef> for LimitArgs {
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
-        let args = ToolArgs::new(&tr.args)?;
-        args.check_named_args(&[intern("skip")])?;
+        let args = ToolArgs::new(tr, &SPEC)?;
 
-        let count = args.required_positional_integer(0, "count")? as usize;
-        let skip  = args.optional_integer(intern("skip"))?.unwrap_or(0) as usize;
+        let count = args.integer("count")? as usize;
+        let skip  = args.integer("skip")? as usize;
 
         Ok(LimitArgs { count, skip })
     }
@@ -1,4 +1,6 @@
+mod aggregate;
 mod args;
+mod cache;
 mod count;
 mod describe;
 mod drop;
@@ -13,18 +15,22 @@ mod output;
 mod print;
 mod project;
 mod register;
+mod route;
 mod schema;
 mod select;
 mod sort;
+mod spec;
 mod sql;
 mod union;
 mod values;
+mod window;
 
 pub mod tool;
 
 pub use args::ToolArgs;
-pub use tool::{FlowRef, Tool};
+pub use spec::{did_you_mean, ArgSpec, ArgType, Literal, ToolSpec};
+pub use tool::{spec, FlowRef, Resource, Tool};
 pub use values::Values;
 
-pub use anvil_parse::anvil::ast::{ArgValue, Flow, FlowItem, ToolArg, ToolId, ToolRef};
-pub use anvil_parse::parse_expression;
\ No newline at end of file
+pub use anvil_parse::anvil::ast::{ArgValue, Flow, Span, ToolArg, ToolId, ToolRef};
+pub use anvil_parse::{parse_expression, parse_expressions, parse_sort_keys};
\ No newline at end of file
@@ -1,11 +1,26 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Result};
 use datafusion::dataframe::DataFrameWriteOptions;
 use datafusion::logical_expr::logical_plan::dml::InsertOp;
 
-use anvil_context::intern;
-use crate::tools::{ToolArgs, ToolId, ToolRef, Values};
+use crate::tools::{ArgSpec, ArgType, Literal, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "output",
+    description: "Write a file",
+    positional: &[
+        ArgSpec { name: "path", ty: ArgType::String, required: true, default: None, values: &[], description: "File or directory to write" },
+    ],
+    keyword: &[
+        ArgSpec { name: "format", ty: ArgType::String, required: false, default: None, values: &["csv", "json", "parquet"], description: "File format, from the extension if omitted" },
+        ArgSpec { name: "mode", ty: ArgType::String, required: false, default: Some(Literal::String("append")), values: &["append", "overwrite", "replace"], description: "How to treat existing data" },
+        ArgSpec { name: "single", ty: ArgType::Boolean, required: false, default: Some(Literal::Boolean(true)), values: &[], description: "Write a single file rather than a directory" },
+    ],
+    named: None,
+    inputs: &["*"],
+    outputs: &[],
+};
 
 pub async fn run(id: &ToolId, args: &OutputArgs, inputs: Values) -> Result<Values>
 {
@@ -27,6 +42,10 @@ pub async fn run(id: &ToolId, args: &OutputArgs, inputs: Values) -> Result<Value
     Ok(Values::default())
 }
 
+pub fn path(args: &OutputArgs) -> PathBuf
+{
+    PathBuf::from(&args.path)
+}
 
 #[allow(non_camel_case_types)]
 #[derive(Debug)]
@@ -49,14 +68,13 @@ impl TryFrom<&ToolRef> for OutputArgs {
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
-        let args = ToolArgs::new(&tr.args)?;
-        args.check_named_args(&[intern("format")])?;
+        let args = ToolArgs::new(tr, &SPEC)?;
 
-        let path   = args.required_positional_string(0, "path")?;
+        let path   = args.string("path")?;
         let fpath  = Path::new(&path);
-        let single = args.optional_bool(intern("single"))?.unwrap_or(true);
+        let single = args.boolean("single")?;
 
-        let format = args.optional_string(intern("format"))?;
+        let format = args.optional_string("format")?;
         let format = match format {
             Some(s) => {
                 match s.as_str() {
@@ -81,7 +99,7 @@ impl TryFrom<&ToolRef> for OutputArgs {
             }
         };
 
-        let mode = args.optional_string(intern("mode"))?.unwrap_or_else(|| "append".into());
+        let mode = args.string("mode")?;
         let mode = match mode.as_str() {
             "append"    => InsertOp::Append,
             "overwrite" => InsertOp::Overwrite,
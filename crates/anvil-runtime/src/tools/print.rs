@@ -1,6 +1,18 @@
 use anyhow::{anyhow, Result};
 
-use crate::tools::{ToolArgs, ToolId, ToolRef, Values};
+use crate::tools::{ArgSpec, ArgType, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "print",
+    description: "Print rows, passing the input through",
+    positional: &[
+        ArgSpec { name: "limit", ty: ArgType::Integer, required: false, default: None, values: &[], description: "Number of rows to print, all if omitted" },
+    ],
+    keyword: &[],
+    named: None,
+    inputs: &["*"],
+    outputs: &["*"],
+};
 
 pub async fn run(id: &ToolId, args: &PrintArgs, inputs: Values) -> Result<Values>
 {
@@ -26,8 +38,8 @@ impl TryFrom<&ToolRef> for PrintArgs {
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
-        let args  = ToolArgs::new(&tr.args)?;
-        let limit = args.optional_positional_integer(0, "limit")?;
+        let args  = ToolArgs::new(tr, &SPEC)?;
+        let limit = args.optional_integer("limit")?;
 
         Ok(PrintArgs { limit })
     }
@@ -3,7 +3,17 @@ use datafusion::prelude::{Expr, SessionContext};
 
 use anvil_context::resolve;
 use crate::eval_expression;
-use crate::tools::{parse_expression, ArgValue, ToolArg, ToolId, ToolRef, Values};
+use crate::tools::{parse_expression, ArgSpec, ArgType, ArgValue, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "project",
+    description: "Compute new columns from expressions",
+    positional: &[],
+    keyword: &[],
+    named: Some(ArgSpec { name: "<column>", ty: ArgType::Expression, required: false, default: None, values: &[], description: "Expression for a new column" }),
+    inputs: &["*"],
+    outputs: &["*"],
+};
 
 pub async fn run(_id: &ToolId, args: &ProjectArgs, inputs: Values, ctx: &SessionContext) -> Result<Values>
 {
@@ -27,23 +37,16 @@ impl TryFrom<&ToolRef> for ProjectArgs {
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
+        let args = ToolArgs::new(tr, &SPEC)?;
+
         let mut exprs = Vec::new();
-        for arg in &tr.args {
-            match arg {
-                ToolArg::Positional(_) => {
-                    return Err(anyhow!("projection tool only accepts keyword arguments"))
-                }
-                ToolArg::Keyword { ident, value } => {
-                    match value {
-                        ArgValue::String(s) => {
-                            let expr  = parse_expression(s)?;
-                            let right = eval_expression(&expr)?;
-                            exprs.push(right.alias(resolve(*ident)));
-                        }
-                        _ => return Err(anyhow!("projection tool expression must be a string {value:?}"))
-                    }
-                }
-            }
+        for (ident, value) in args.named() {
+            let ArgValue::String(s) = value else {
+                return Err(anyhow!("projection tool expression must be a string {value:?}"))
+            };
+            let expr  = parse_expression(s)?;
+            let right = eval_expression(&expr)?;
+            exprs.push(right.alias(resolve(ident)));
         }
 
         Ok(ProjectArgs { exprs })
@@ -1,31 +1,73 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Result};
+use datafusion::common::TableReference;
 use datafusion::execution::context::SessionContext;
 use datafusion::execution::options::ArrowReadOptions;
 use datafusion::prelude::{AvroReadOptions, CsvReadOptions, NdJsonReadOptions, ParquetReadOptions};
 
-use anvil_context::intern;
-use crate::tools::{ToolArgs, ToolId, ToolRef, Values};
+use crate::tools::{ArgSpec, ArgType, Literal, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
 
-pub async fn run(_id: &ToolId, args: &RegisterArgs, ctx: &SessionContext) -> Result<Values>
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "register",
+    description: "Register a file or the input as a table for SQL queries",
+    positional: &[
+        ArgSpec { name: "path", ty: ArgType::String, required: false, default: None, values: &[], description: "File or directory to register, the input if omitted" },
+    ],
+    keyword: &[
+        ArgSpec { name: "format", ty: ArgType::String, required: false, default: None, values: &["csv", "avro", "json", "arrow", "parquet"], description: "File format, from the extension if omitted" },
+        ArgSpec { name: "table", ty: ArgType::String, required: false, default: Some(Literal::String("tbl")), values: &[], description: "Table name" },
+    ],
+    named: None,
+    inputs: &["*"],
+    outputs: &["*"],
+};
+
+pub async fn run(id: &ToolId, args: &RegisterArgs, inputs: Option<Values>, ctx: &SessionContext) -> Result<Values>
 {
     use Format::*;
 
-    let (path, table) = (&args.path, &args.table);
-    match args.format {
-        csv     => ctx.register_csv(table, path, CsvReadOptions::default()).await?,
-        avro    => ctx.register_avro(table, path, AvroReadOptions::default()).await?,
-        json    => ctx.register_json(table, path, NdJsonReadOptions::default()).await?,
-        arrow   => ctx.register_arrow(table, path, ArrowReadOptions::default()).await?,
-        parquet => ctx.register_parquet(table, path, ParquetReadOptions::default()).await?,
-    };
+    let table = &args.table;
+    match (&args.path, inputs) {
+        (Some(path), None) => {
+            match args.format {
+                csv     => ctx.register_csv(table, path, CsvReadOptions::default()).await?,
+                avro    => ctx.register_avro(table, path, AvroReadOptions::default()).await?,
+                json    => ctx.register_json(table, path, NdJsonReadOptions::default()).await?,
+                arrow   => ctx.register_arrow(table, path, ArrowReadOptions::default()).await?,
+                parquet => ctx.register_parquet(table, path, ParquetReadOptions::default()).await?,
+            };
+        }
+        (None, Some(inputs)) => {
+            let df = inputs.get_one().cloned()
+                .ok_or_else(|| anyhow!("register tool ({id}) requires input"))?;
+            ctx.register_table(table, df.into_view())?;
+        }
+        (Some(_), Some(_)) => {
+            return Err(anyhow!("register tool ({id}) takes either a path or input, not both"))
+        }
+        (None, None) => {
+            return Err(anyhow!("register tool ({id}) requires a path or input"))
+        }
+    }
 
     let df = ctx.table(table).await?;
 
     Ok(Values::new(df))
 }
 
+/// Name of the table being registered, normalized the same way
+/// table references in SQL statements are.
+pub fn table(args: &RegisterArgs) -> String
+{
+    TableReference::from(args.table.as_str()).table().to_string()
+}
+
+pub fn path(args: &RegisterArgs) -> Option<PathBuf>
+{
+    args.path.as_ref().map(PathBuf::from)
+}
+
 #[derive(Debug)]
 #[allow(non_camel_case_types)]
 enum Format {
@@ -39,25 +81,30 @@ enum Format {
 #[derive(Debug)]
 pub struct RegisterArgs {
     format: Format,
-    path: String,
+    path: Option<String>,
     table: String,
 }
 
+impl RegisterArgs {
+    pub fn is_source(&self) -> bool
+    {
+        self.path.is_some()
+    }
+}
+
 impl TryFrom<&ToolRef> for RegisterArgs {
     type Error = anyhow::Error;
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
-        let args = ToolArgs::new(&tr.args)?;
-        args.check_named_args(&[intern("format"), intern("table")])?;
+        let args = ToolArgs::new(tr, &SPEC)?;
 
-        let path = args.required_positional_string(0, "input: path")?;
-        let fpath = Path::new(&path);
-        if !fpath.exists() {
-            return Err(anyhow!("input file not found: {}", fpath.display()));
-        }
+        let path = args.optional_string("path")?;
+        let ext  = path.as_ref()
+            .and_then(|p| Path::new(p).extension())
+            .and_then(|s| s.to_str());
 
-        let format = args.optional_string(intern("format"))?;
+        let format = args.optional_string("format")?;
         let format = match format {
             Some(s) => {
                 match s.as_str() {
@@ -72,21 +119,16 @@ impl TryFrom<&ToolRef> for RegisterArgs {
                 }
             }
             None => {
-                if let Some(s) = fpath.extension() {
-                    match s.to_str() {
-                        Some("csv")  => Format::csv,
-                        Some("json") => Format::json,
-                        _            => Format::parquet
-                    }
-                } else {
-                    Format::parquet
+                match ext {
+                    Some("csv")  => Format::csv,
+                    Some("json") => Format::json,
+                    _            => Format::parquet
                 }
             }
         };
 
-        let table = args.optional_string(intern("table"))?
-            .unwrap_or("tbl".into());
+        let table = args.string("table")?;
 
         Ok(RegisterArgs { format, path, table })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,87 @@
+use anyhow::{anyhow, Result};
+use datafusion::prelude::{lit, Expr};
+
+use anvil_context::{resolve, syms, Symbol};
+use crate::eval_expression;
+use crate::tools::{parse_expression, ArgSpec, ArgType, ArgValue, Literal, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "route",
+    description: "Split rows between outputs named by predicates",
+    positional: &[],
+    keyword: &[
+        ArgSpec { name: "mode", ty: ArgType::String, required: false, default: Some(Literal::String("first")), values: &["first", "all"], description: "Rows go to the first output they match, or all of them" },
+    ],
+    named: Some(ArgSpec { name: "<output>", ty: ArgType::Expression, required: false, default: None, values: &[], description: "Boolean expression for the rows of an output" }),
+    inputs: &["*"],
+    outputs: &["<output>", "else"],
+};
+
+pub async fn run(id: &ToolId, args: &RouteArgs, inputs: Values) -> Result<Values>
+{
+    let df = inputs.get_one()
+        .cloned()
+        .ok_or_else(|| anyhow!("route tool ({id}) requires input"))?;
+
+    // Rows a predicate is null for don't match it, as with filter
+    let mut values = Values::default();
+    let mut unmatched = lit(true);
+    for (port, predicate) in &args.routes {
+        let expr = if args.first {
+            unmatched.clone().and(predicate.clone().is_true())
+        } else {
+            predicate.clone().is_true()
+        };
+        values.set(*port, df.clone().filter(expr)?);
+        unmatched = unmatched.and(predicate.clone().is_not_true());
+    }
+    values.set(syms().port_else, df.filter(unmatched)?);
+
+    Ok(values)
+}
+
+/// Ports of a route, one per predicate in the order they're written
+/// and then `else`.
+pub fn outputs(args: &RouteArgs) -> Vec<Symbol>
+{
+    args.routes.iter()
+        .map(|(port, _)| *port)
+        .chain([syms().port_else])
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct RouteArgs {
+    /// Output ports and the predicates of their rows
+    routes: Vec<(Symbol, Expr)>,
+    /// Whether a row only goes to the first output it matches
+    first: bool,
+}
+
+impl TryFrom<&ToolRef> for RouteArgs {
+    type Error = anyhow::Error;
+
+    fn try_from(tr: &ToolRef) -> Result<Self>
+    {
+        let args = ToolArgs::new(tr, &SPEC)?;
+
+        let first = args.string("mode")? == "first";
+
+        let mut routes = vec![];
+        for (port, value) in args.named() {
+            if port == syms().port_else {
+                return Err(anyhow!("route tool output 'else' holds the unmatched rows, it can't have a predicate"))
+            }
+            let ArgValue::String(s) = value else {
+                return Err(anyhow!("route tool predicate of '{}' must be a string", resolve(port)))
+            };
+            let expr = eval_expression(&parse_expression(s)?)?;
+            routes.push((port, expr));
+        }
+        if routes.is_empty() {
+            return Err(anyhow!("route tool needs a predicate, e.g. [route: big='$n > 100']"))
+        }
+
+        Ok(RouteArgs { routes, first })
+    }
+}
@@ -4,7 +4,17 @@ use anyhow::{anyhow, Result};
 use datafusion::prelude::*;
 use datafusion::common::arrow::array::{BooleanArray, UInt64Array, StringArray};
 
-use crate::tools::{ToolId, Values};
+use crate::tools::{ToolId, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "schema",
+    description: "Column names, types and nullability",
+    positional: &[],
+    keyword: &[],
+    named: None,
+    inputs: &["*"],
+    outputs: &["*"],
+};
 
 pub async fn run(id: &ToolId, inputs: Values) -> Result<Values>
 {
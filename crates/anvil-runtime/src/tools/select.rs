@@ -1,7 +1,19 @@
 use anyhow::{anyhow, Result};
 use datafusion::prelude::{col, Expr};
 
-use crate::tools::{ToolArgs, ToolId, ToolRef, Values};
+use crate::tools::{ArgSpec, ArgType, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "select",
+    description: "Keep columns, optionally renaming them",
+    positional: &[
+        ArgSpec { name: "cols", ty: ArgType::List, required: true, default: None, values: &[], description: "Columns, 'col:alias' to rename" },
+    ],
+    keyword: &[],
+    named: None,
+    inputs: &["*"],
+    outputs: &["*"],
+};
 
 pub async fn run(id: &ToolId, args: &SelectArgs, inputs: Values) -> Result<Values>
 {
@@ -22,11 +34,10 @@ impl TryFrom<&ToolRef> for SelectArgs {
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
-        let args = ToolArgs::new(&tr.args)?;
-        args.check_named_args(&[])?;
+        let args = ToolArgs::new(tr, &SPEC)?;
 
-        let cols  = args.required_positional_string(0, "cols")?;
-        let exprs = cols.split(',')
+        let cols  = args.strings("cols")?;
+        let exprs = cols.iter()
             .map(|s| {
                 match s.split_once(':') {
                     Some((s1, s2)) => col(s1).alias(s2),
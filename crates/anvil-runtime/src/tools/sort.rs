@@ -2,7 +2,19 @@ use anyhow::{anyhow, Result};
 use datafusion::prelude::col;
 use datafusion::logical_expr::SortExpr;
 
-use crate::tools::{ToolArgs, ToolId, ToolRef, Values};
+use crate::tools::{ArgSpec, ArgType, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "sort",
+    description: "Sort rows",
+    positional: &[
+        ArgSpec { name: "cols", ty: ArgType::List, required: true, default: None, values: &[], description: "Sort keys, 'col:asc:nulls_first'" },
+    ],
+    keyword: &[],
+    named: None,
+    inputs: &["*"],
+    outputs: &["*"],
+};
 
 pub async fn run(id: &ToolId, args: &SortArgs, inputs: Values) -> Result<Values>
 {
@@ -23,11 +35,10 @@ impl TryFrom<&ToolRef> for SortArgs {
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
-        let args = ToolArgs::new(&tr.args)?;
-        args.check_named_args(&[])?;
+        let args = ToolArgs::new(tr, &SPEC)?;
 
-        let cols = args.required_positional_string(0, "cols")?;
-        let exprs = cols.split(',')
+        let cols = args.strings("cols")?;
+        let exprs = cols.iter()
             .map(|s| {
                 let parts = s.splitn(3, ':').collect::<Vec<_>>();
                 if parts.is_empty() || parts[0].is_empty() {
@@ -0,0 +1,314 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+
+use crate::tools::ArgValue;
+
+/// Type of value an argument accepts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArgType {
+    Any,
+    Boolean,
+    Expression,
+    Flow,
+    Integer,
+    /// List of strings, or a string of them separated by commas
+    List,
+    String,
+}
+
+impl ArgType {
+    pub fn accepts(&self, value: &ArgValue) -> bool
+    {
+        match self {
+            ArgType::Any        => true,
+            ArgType::Boolean    => matches!(value, ArgValue::Boolean(_)),
+            ArgType::Expression => matches!(value, ArgValue::String(_)),
+            ArgType::Integer    => matches!(value, ArgValue::Integer(_)),
+            ArgType::String     => matches!(value, ArgValue::String(_)),
+            ArgType::List => match value {
+                ArgValue::List(values) => values.iter().all(|v| matches!(v, ArgValue::String(_))),
+                value => matches!(value, ArgValue::String(_)),
+            },
+            ArgType::Flow => {
+                matches!(value, ArgValue::Flow(_) | ArgValue::Ident(..) | ArgValue::String(_))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ArgType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        let name = match self {
+            ArgType::Any        => "any",
+            ArgType::Boolean    => "boolean",
+            ArgType::Expression => "expression",
+            ArgType::Flow       => "flow",
+            ArgType::Integer    => "integer",
+            ArgType::List       => "list",
+            ArgType::String     => "string",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// Default value of an argument.
+#[derive(Clone, Copy, Debug)]
+pub enum Literal {
+    Boolean(bool),
+    Integer(i64),
+    String(&'static str),
+}
+
+impl From<Literal> for ArgValue {
+    fn from(literal: Literal) -> ArgValue
+    {
+        match literal {
+            Literal::Boolean(b) => ArgValue::Boolean(b),
+            Literal::Integer(n) => ArgValue::Integer(n),
+            Literal::String(s)  => ArgValue::String(s.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Literal::Boolean(b) => write!(f, "{b}"),
+            Literal::Integer(n) => write!(f, "{n}"),
+            Literal::String(s)  => write!(f, "'{s}'"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub ty: ArgType,
+    pub required: bool,
+    /// Value used when the argument is omitted.
+    pub default: Option<Literal>,
+    /// Allowed string values, any value if empty.
+    pub values: &'static [&'static str],
+    pub description: &'static str,
+}
+
+impl ArgSpec {
+    /// Check the value's type and, for enumerated arguments, that it
+    /// is one of the allowed values.
+    pub fn check(&self, tool: &str, value: &ArgValue) -> Result<()>
+    {
+        if !self.ty.accepts(value) {
+            return Err(anyhow!("{tool} tool '{}' must be of type {}", self.name, self.ty))
+        }
+
+        if let ArgValue::String(s) = value
+            && !self.values.is_empty()
+            && !self.values.contains(&s.as_str())
+        {
+            let values = self.values.join(", ");
+            return Err(anyhow!(
+                "{tool} tool '{}' must be one of {values}: '{s}'{}",
+                self.name, did_you_mean(s, self.values.iter().copied())
+            ))
+        }
+
+        Ok(())
+    }
+}
+
+/// Description of a tool's arguments and ports, used both to render
+/// help and to validate the arguments a tool is given.
+#[derive(Debug)]
+pub struct ToolSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub positional: &'static [ArgSpec],
+    pub keyword: &'static [ArgSpec],
+    /// Keyword arguments with arbitrary names, e.g. the new columns of
+    /// a projection.
+    pub named: Option<ArgSpec>,
+    pub inputs: &'static [&'static str],
+    pub outputs: &'static [&'static str],
+}
+
+impl ToolSpec {
+    /// Spec of a positional or keyword argument.
+    pub fn arg(&self, name: &str) -> Option<&ArgSpec>
+    {
+        self.positional.iter()
+            .chain(self.keyword)
+            .find(|arg| arg.name == name)
+    }
+
+    /// Spec of a keyword argument, including arbitrarily named ones.
+    pub fn keyword(&self, name: &str) -> Option<&ArgSpec>
+    {
+        self.keyword.iter()
+            .find(|arg| arg.name == name)
+            .or(self.named.as_ref())
+    }
+
+    /// Usage line, e.g. `[limit: count, skip=0]`.
+    pub fn usage(&self) -> String
+    {
+        let mut args = self.positional.iter()
+            .map(|arg| if arg.required { arg.name.to_string() } else { format!("{}?", arg.name) })
+            .collect::<Vec<_>>();
+        for arg in self.keyword {
+            match &arg.default {
+                Some(value) => args.push(format!("{}={value}", arg.name)),
+                None => args.push(format!("{}=...", arg.name)),
+            }
+        }
+        if let Some(arg) = &self.named {
+            args.push(format!("{}=...", arg.name));
+        }
+
+        if args.is_empty() {
+            format!("[{}]", self.name)
+        } else {
+            format!("[{}: {}]", self.name, args.join(", "))
+        }
+    }
+}
+
+impl fmt::Display for ToolSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        writeln!(f, "{} - {}", self.name, self.description)?;
+        writeln!(f)?;
+        writeln!(f, "usage: {}", self.usage())?;
+
+        let sections = [
+            ("positional arguments", self.positional),
+            ("keyword arguments", self.keyword),
+        ];
+        for (title, args) in sections {
+            if !args.is_empty() {
+                writeln!(f)?;
+                writeln!(f, "{title}:")?;
+                for arg in args {
+                    write_arg(f, arg)?;
+                }
+            }
+        }
+        if let Some(arg) = &self.named {
+            if self.keyword.is_empty() {
+                writeln!(f)?;
+                writeln!(f, "keyword arguments:")?;
+            }
+            write_arg(f, arg)?;
+        }
+
+        let ports = |ports: &[&str]| {
+            if ports.is_empty() { "none".to_string() } else { ports.join(", ") }
+        };
+        writeln!(f)?;
+        writeln!(f, "inputs:  {}", ports(self.inputs))?;
+        write!(f, "outputs: {}", ports(self.outputs))
+    }
+}
+
+fn write_arg(f: &mut fmt::Formatter<'_>, arg: &ArgSpec) -> fmt::Result
+{
+    let detail = match (arg.required, arg.default) {
+        (true, _) => "required".to_string(),
+        (false, Some(value)) => format!("default {value}"),
+        (false, None) => "optional".to_string(),
+    };
+
+    write!(f, "  {:<10} {:<10} {:<16} {}", arg.name, arg.ty.to_string(), detail, arg.description)?;
+    if arg.values.is_empty() {
+        writeln!(f)
+    } else {
+        writeln!(f, ": {}", arg.values.join(", "))
+    }
+}
+
+/// A ", did you mean 'x'?" hint naming the candidate closest to a
+/// misspelt name, or nothing if none are close.
+pub fn did_you_mean<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> String
+{
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .map(|c| (distance(name, c), c))
+        .filter(|(d, _)| *d <= limit)
+        .min()
+        .map(|(_, c)| format!(", did you mean '{c}'?"))
+        .unwrap_or_default()
+}
+
+/// Edit distance counting adjacent transpositions as one edit, so
+/// 'fitler' is one edit from 'filter'.
+fn distance(a: &str, b: &str) -> usize
+{
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_counts_edits()
+    {
+        assert_eq!(distance("filter", "filter"), 0);
+        assert_eq!(distance("", "sort"), 4);
+        assert_eq!(distance("sort", ""), 4);
+        assert_eq!(distance("fliter", "filter"), 1);
+        assert_eq!(distance("filtr", "filter"), 1);
+        assert_eq!(distance("filterr", "filter"), 1);
+        assert_eq!(distance("fitler", "filter"), 1);
+        assert_eq!(distance("sort", "join"), 3);
+    }
+
+    #[test]
+    fn distance_counts_chars_not_bytes()
+    {
+        assert_eq!(distance("naïve", "naive"), 1);
+    }
+
+    #[test]
+    fn did_you_mean_picks_closest()
+    {
+        let tools = ["filter", "fill", "select", "sort"];
+        assert_eq!(did_you_mean("fitler", tools.into_iter()), ", did you mean 'filter'?");
+        assert_eq!(did_you_mean("sor", tools.into_iter()), ", did you mean 'sort'?");
+    }
+
+    #[test]
+    fn did_you_mean_nothing_close()
+    {
+        let tools = ["filter", "select", "sort"];
+        assert_eq!(did_you_mean("aggregate", tools.into_iter()), "");
+        assert_eq!(did_you_mean("x", tools.into_iter()), "");
+        assert_eq!(did_you_mean("filter", std::iter::empty()), "");
+    }
+}
@@ -1,13 +1,47 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+
 use anyhow::{anyhow, Result};
-use datafusion::prelude::SessionContext;
+use async_trait::async_trait;
+use datafusion::catalog::{
+    CatalogProvider, CatalogProviderList, MemoryCatalogProvider, MemoryCatalogProviderList, SchemaProvider,
+    TableFunction, TableProvider,
+};
+use datafusion::common::TableReference;
+use datafusion::execution::SessionStateBuilder;
+use datafusion::prelude::{DataFrame, SessionContext};
+use datafusion::sql::parser::{DFParser, Statement};
+use datafusion::sql::planner::object_name_to_table_reference;
+use datafusion::sql::resolve::resolve_table_references;
+use datafusion::sql::sqlparser::ast::{self, ObjectName};
+
+use anvil_context::{intern, resolve, Symbol};
+use crate::tools::{ArgSpec, ArgType, ArgValue, Flow, FlowRef, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+static TABLE_FUNCTIONS: LazyLock<Vec<Arc<TableFunction>>> =
+    LazyLock::new(datafusion::functions_table::all_default_table_functions);
 
-use anvil_context::{resolve, Symbol};
-use crate::tools::{ArgValue, ToolArg, ToolId, ToolRef, Values};
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "sql",
+    description: "Run a SQL query or compute columns from SQL expressions",
+    positional: &[
+        ArgSpec { name: "query", ty: ArgType::String, required: false, default: None, values: &[], description: "SQL query against registered tables" },
+    ],
+    keyword: &[],
+    named: Some(ArgSpec { name: "<name>", ty: ArgType::Any, required: false, default: None, values: &[], description: "SQL expression for a new column, or a flow queried as a table" }),
+    inputs: &["*"],
+    outputs: &["*"],
+};
 
 pub async fn run(id: &ToolId, args: &SqlArgs, inputs: Option<Values>, ctx: &SessionContext) -> Result<Values>
 {
     let df = if let Some(sql) = &args.sql {
-        ctx.sql(sql).await?
+        if args.tables.is_empty() {
+            ctx.sql(sql).await?
+        } else {
+            query_tables(id, sql, &args.tables, inputs, ctx).await?
+        }
     } else if let Some(v) = inputs {
         let df = v.get_one().unwrap();
         let mut exprs = vec![];
@@ -23,10 +57,165 @@ pub async fn run(id: &ToolId, args: &SqlArgs, inputs: Option<Values>, ctx: &Sess
     Ok(Values::new(df))
 }
 
+pub fn flows(args: &SqlArgs) -> Vec<FlowRef>
+{
+    args.tables.iter()
+        .map(|(name, flow)| FlowRef { port: *name, flow: flow.clone() })
+        .collect()
+}
+
+/// Whether the tool runs a query, which may have side effects such as
+/// creating tables, rather than computing columns of its input.
+pub fn is_query(args: &SqlArgs) -> bool
+{
+    args.sql.is_some()
+}
+
+/// Tables the query reads from the session, i.e. those not passed
+/// in as table arguments.
+pub fn tables(args: &SqlArgs) -> &[String]
+{
+    &args.reads
+}
+
+/// Tables and views the query creates in the session.
+pub fn creates(args: &SqlArgs) -> &[String]
+{
+    &args.creates
+}
+
+/// Name of the table or view a statement creates, if it does.
+fn created(stmt: &Statement) -> Option<&ObjectName>
+{
+    match stmt {
+        Statement::Statement(stmt) => match stmt.as_ref() {
+            ast::Statement::CreateView { name, .. } => Some(name),
+            ast::Statement::CreateTable(create) => Some(&create.name),
+            _ => None,
+        },
+        Statement::CreateExternalTable(create) => Some(&create.name),
+        _ => None,
+    }
+}
+
+/// Unqualified table names excluding table functions such as
+/// generate_series, which appear as relations in the statement.
+fn bare_table(table: &TableReference) -> Option<&str>
+{
+    match table {
+        TableReference::Bare { table } => {
+            let is_func = TABLE_FUNCTIONS.iter().any(|f| f.name() == table.as_ref());
+            (!is_func).then_some(table.as_ref())
+        }
+        _ => None,
+    }
+}
+
+/// Run a query in a session of its own, which sees the table inputs
+/// as views over the tables of the shared session. The shared session
+/// is left as it is, apart from any tables the query creates.
+async fn query_tables(
+    id: &ToolId,
+    sql: &str,
+    tables: &[(Symbol, Flow)],
+    inputs: Option<Values>,
+    ctx: &SessionContext
+) -> Result<DataFrame>
+{
+    let inputs = inputs.unwrap_or_default();
+
+    let mut views = HashMap::new();
+    for (name, _) in tables {
+        let df = inputs.dfs.get(name).cloned()
+            .ok_or_else(|| anyhow!("sql tool ({id}) requires input for table '{}'", resolve(*name)))?;
+        views.insert(resolve(*name).to_string(), df.into_view());
+    }
+
+    // Every catalog and schema is the shared session's, except for the
+    // default schema which has the views in front of it
+    let state = ctx.state();
+    let options = state.config_options().catalog.clone();
+    let shared = state.catalog_list();
+    let default = shared.catalog(&options.default_catalog)
+        .ok_or_else(|| anyhow!("sql tool ({id}) has no catalog '{}'", options.default_catalog))?;
+
+    let catalog = MemoryCatalogProvider::new();
+    for name in default.schema_names() {
+        let schema = default.schema(&name).unwrap();
+        let schema = if name == options.default_schema {
+            Arc::new(QuerySchema { views: views.clone(), shared: schema })
+        } else {
+            schema
+        };
+        catalog.register_schema(&name, schema)?;
+    }
+    let catalogs = MemoryCatalogProviderList::new();
+    for name in shared.catalog_names() {
+        catalogs.register_catalog(name.clone(), shared.catalog(&name).unwrap());
+    }
+    catalogs.register_catalog(options.default_catalog, Arc::new(catalog));
+
+    let state = SessionStateBuilder::new_from_existing(state)
+        .with_catalog_list(Arc::new(catalogs))
+        .build();
+
+    Ok(SessionContext::new_with_state(state).sql(sql).await?)
+}
+
+/// Default schema of a query's session, the query's table inputs in
+/// front of the shared session's schema, where tables it creates go.
+#[derive(Debug)]
+struct QuerySchema {
+    views: HashMap<String, Arc<dyn TableProvider>>,
+    shared: Arc<dyn SchemaProvider>,
+}
+
+#[async_trait]
+impl SchemaProvider for QuerySchema {
+    fn as_any(&self) -> &dyn Any
+    {
+        self
+    }
+
+    fn table_names(&self) -> Vec<String>
+    {
+        let mut names = self.shared.table_names();
+        names.retain(|name| !self.views.contains_key(name));
+        names.extend(self.views.keys().cloned());
+        names
+    }
+
+    async fn table(&self, name: &str) -> datafusion::common::Result<Option<Arc<dyn TableProvider>>>
+    {
+        match self.views.get(name) {
+            Some(view) => Ok(Some(view.clone())),
+            None => self.shared.table(name).await,
+        }
+    }
+
+    fn register_table(&self, name: String, table: Arc<dyn TableProvider>) -> datafusion::common::Result<Option<Arc<dyn TableProvider>>>
+    {
+        self.shared.register_table(name, table)
+    }
+
+    fn deregister_table(&self, name: &str) -> datafusion::common::Result<Option<Arc<dyn TableProvider>>>
+    {
+        self.shared.deregister_table(name)
+    }
+
+    fn table_exist(&self, name: &str) -> bool
+    {
+        self.views.contains_key(name) || self.shared.table_exist(name)
+    }
+}
+
 #[derive(Debug)]
 pub struct SqlArgs {
     sql: Option<String>,
-    exprs: Vec<(Symbol, String)>
+    exprs: Vec<(Symbol, String)>,
+    tables: Vec<(Symbol, Flow)>,
+    reads: Vec<String>,
+    creates: Vec<String>,
 }
 
 impl TryFrom<&ToolRef> for SqlArgs {
@@ -34,25 +223,109 @@ impl TryFrom<&ToolRef> for SqlArgs {
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
-        let mut sql   = None;
-        let mut exprs = Vec::new();
-        for arg in &tr.args {
-            match arg {
-                ToolArg::Positional(value) => {
-                    match value {
-                        ArgValue::String(s) => sql = Some(s.clone()),
-                        _ => return Err(anyhow!("sql tool SQL must be string"))
-                    }
+        let args = ToolArgs::new(tr, &SPEC)?;
+
+        let sql = args.optional_string("query")?;
+        let mut exprs  = Vec::new();
+        let mut tables = Vec::new();
+        for (ident, value) in args.named() {
+            match value {
+                ArgValue::String(s) => exprs.push((ident, s.clone())),
+                ArgValue::Flow(f)   => tables.push((ident, f.clone())),
+                ArgValue::Ident(s, span) => {
+                    tables.push((ident, Flow::variable(intern(s), *span)))
                 }
-                ToolArg::Keyword { ident, value } => {
-                    match value {
-                        ArgValue::String(s) => exprs.push((*ident, s.clone())),
-                        _ => return Err(anyhow!("sql tool expression must be a string {value:?}"))
+                _ => return Err(anyhow!("sql tool expression must be a string {value:?}"))
+            }
+        }
+
+        if !tables.is_empty() && sql.is_none() {
+            return Err(anyhow!("sql tool table arguments require a SQL query"))
+        }
+
+        let mut reads = Vec::new();
+        let mut creates = Vec::new();
+        if let Some(sql) = &sql {
+            for stmt in DFParser::parse_sql(sql)? {
+                if let Some(name) = created(&stmt) {
+                    let table = object_name_to_table_reference(name.clone(), true)?;
+                    creates.extend(bare_table(&table).map(str::to_string));
+                }
+                let (refs, _) = resolve_table_references(&stmt, true)?;
+                for table in refs.iter().filter_map(bare_table) {
+                    if !tables.iter().any(|(name, _)| resolve(*name) == table) && !creates.iter().any(|t| t == table) {
+                        reads.push(table.to_string());
                     }
                 }
             }
         }
 
-        Ok(SqlArgs { sql, exprs })
+        Ok(SqlArgs { sql, exprs, tables, reads, creates })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::prelude::col;
+
+    use anvil_context::intern;
+    use anvil_parse::{ASTBuilder, Sources};
+    use crate::{run, Executor, Planner};
+
+    /// Values of column `n` bound to `var` by a script.
+    async fn column(script: &str, var: &str) -> Vec<i64>
+    {
+        let mut builder = ASTBuilder::default();
+        let mut planner = Planner::default();
+        let mut executor = Executor::default();
+        run(&mut builder, &mut planner, &mut executor, &mut Sources::default(), script, Path::new(".")).await.unwrap();
+
+        let ix = planner.vars()[&intern(var)];
+        let df = executor.values(ix).unwrap().get_one().unwrap().clone();
+        let mut values = vec![];
+        for batch in df.sort_by(vec![col("n")]).unwrap().collect().await.unwrap() {
+            let n = batch.column_by_name("n").unwrap();
+            let n = n.as_any().downcast_ref::<Int64Array>().unwrap();
+            values.extend(n.iter().flatten());
+        }
+        values
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn table_input_shadows_session_table()
+    {
+        let script = "
+            [sql: 'CREATE VIEW t AS SELECT CAST(1 AS BIGINT) AS n'];
+            [sql: 'SELECT * FROM t', t=([sql: 'SELECT CAST(2 AS BIGINT) AS n'])] > shadowed;
+            [sql: 'SELECT * FROM t'] > original;
+        ";
+        assert_eq!(column(script, "shadowed").await, [2]);
+        assert_eq!(column(script, "original").await, [1]);
+    }
+
+    #[tokio::test]
+    async fn sibling_table_inputs()
+    {
+        let script = "
+            [sql: 'SELECT * FROM a', a=([sql: 'SELECT CAST(1 AS BIGINT) AS n'])] > x;
+            [sql: 'SELECT * FROM a', a=([sql: 'SELECT CAST(2 AS BIGINT) AS n'])] > y;
+            [sql: 'SELECT * FROM a', a=([sql: 'SELECT CAST(3 AS BIGINT) AS n'])] > z;
+        ";
+        assert_eq!(column(script, "x").await, [1]);
+        assert_eq!(column(script, "y").await, [2]);
+        assert_eq!(column(script, "z").await, [3]);
+    }
+
+    #[tokio::test]
+    async fn created_from_table_input()
+    {
+        let script = "
+            [sql: 'CREATE VIEW v AS SELECT n + 1 AS n FROM a', a=([sql: 'SELECT CAST(1 AS BIGINT) AS n'])];
+            [sql: 'SELECT * FROM v'] > created;
+        ";
+        assert_eq!(column(script, "created").await, [2]);
+    }
+}
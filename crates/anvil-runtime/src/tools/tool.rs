@@ -1,8 +1,11 @@
+use std::path::{Component, PathBuf};
+
 use anyhow::{anyhow, Result};
 use datafusion::prelude::SessionContext;
 
-use anvil_context::{resolve, tool_types, Symbol, ToolType};
+use anvil_context::{intern, resolve, tool_types, Symbol, ToolType};
 use crate::tools::*;
+use crate::tools::did_you_mean;
 
 
 #[derive(Debug)]
@@ -11,8 +14,39 @@ pub struct FlowRef {
     pub flow: Flow,
 }
 
+/// Named state outside the plan's dataflow that tools create or
+/// depend on, e.g. registered tables and files on disk.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Resource {
+    Path(PathBuf),
+    Table(String),
+}
+
+impl Resource {
+    /// A file, its path made absolute and `.` and `..` removed without
+    /// touching the file system, so different ways of writing the
+    /// same path name the same resource before the file exists.
+    pub fn path(path: PathBuf) -> Resource
+    {
+        let path = std::path::absolute(&path).unwrap_or(path);
+
+        let mut clean = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => { clean.pop(); }
+                component => clean.push(component),
+            }
+        }
+
+        Resource::Path(clean)
+    }
+}
+
 #[derive(Debug)]
 pub enum Tool {
+    Aggregate((ToolId, aggregate::AggregateArgs)),
+    Cache(ToolId),
     Count((ToolId, count::CountArgs)),
     Describe(ToolId),
     Distinct(ToolId),
@@ -27,11 +61,13 @@ pub enum Tool {
     Print((ToolId, print::PrintArgs)),
     Project((ToolId, project::ProjectArgs)),
     Register((ToolId, register::RegisterArgs)),
+    Route((ToolId, route::RouteArgs)),
     Schema(ToolId),
     Select((ToolId, select::SelectArgs)),
     Sort((ToolId, sort::SortArgs)),
     Sql((ToolId, sql::SqlArgs)),
     Union((ToolId, union::UnionArgs)),
+    Window((ToolId, window::WindowArgs)),
 }
 
 impl TryFrom<&ToolRef> for Tool {
@@ -43,9 +79,11 @@ impl TryFrom<&ToolRef> for Tool {
 
         let name = tr.name;
         let tool = match tool_types().get(&tr.name) {
+            Some(Aggregate) => Tool::Aggregate((tr.id, tr.try_into()?)),
+            Some(Cache)     => Tool::Cache(no_args(tr, &cache::SPEC)?),
             Some(Count)     => Tool::Count((tr.id, tr.try_into()?)),
-            Some(Describe)  => Tool::Describe(tr.id),
-            Some(Distinct)  => Tool::Distinct(tr.id),
+            Some(Describe)  => Tool::Describe(no_args(tr, &describe::SPEC)?),
+            Some(Distinct)  => Tool::Distinct(no_args(tr, &distinct::SPEC)?),
             Some(Drop)      => Tool::Drop((tr.id, tr.try_into()?)),
             Some(Fill)      => Tool::Fill((tr.id, tr.try_into()?)),
             Some(Filter)    => Tool::Filter((tr.id, tr.try_into()?)),
@@ -57,12 +95,17 @@ impl TryFrom<&ToolRef> for Tool {
             Some(Print)     => Tool::Print((tr.id, tr.try_into()?)),
             Some(Project)   => Tool::Project((tr.id, tr.try_into()?)),
             Some(Register)  => Tool::Register((tr.id, tr.try_into()?)),
-            Some(Schema)    => Tool::Schema(tr.id),
+            Some(Route)     => Tool::Route((tr.id, tr.try_into()?)),
+            Some(Schema)    => Tool::Schema(no_args(tr, &schema::SPEC)?),
             Some(Select)    => Tool::Select((tr.id, tr.try_into()?)),
             Some(Sort)      => Tool::Sort((tr.id, tr.try_into()?)),
             Some(Sql)       => Tool::Sql((tr.id, tr.try_into()?)),
             Some(Union)     => Tool::Union((tr.id, tr.try_into()?)),
-            _ => return Err(anyhow!("unknown tool: {}", resolve(name)))
+            Some(Window)    => Tool::Window((tr.id, tr.try_into()?)),
+            _ => {
+                let names = tool_types().keys().map(|s| resolve(*s));
+                return Err(anyhow!("unknown tool: {}{}", resolve(name), did_you_mean(resolve(name), names)))
+            }
         };
 
         Ok(tool)
@@ -81,7 +124,7 @@ impl Tool {
 
             match self {
                 Tool::Input((id, args))    => input::run(id, args, ctx).await?,
-                Tool::Register((id, args)) => register::run(id, args, ctx).await?,
+                Tool::Register((id, args)) => register::run(id, args, None, ctx).await?,
                 _ => unreachable!("{} ({}) is not a source tool", self.name(), self.id())
             }
         } else if inputs.is_none() {
@@ -89,6 +132,8 @@ impl Tool {
         } else {
             let inputs = inputs.unwrap();
             match self {
+                Tool::Aggregate((id, args)) => aggregate::run(id, args, inputs).await?,
+                Tool::Cache(id)           => cache::run(id, inputs).await?,
                 Tool::Count((id, args))   => count::run(id, args, inputs, ctx).await?,
                 Tool::Describe(id)        => describe::run(id, inputs).await?,
                 Tool::Distinct(id)        => distinct::run(id, inputs).await?,
@@ -101,10 +146,13 @@ impl Tool {
                 Tool::Output((id, args))  => output::run(id, args, inputs).await?,
                 Tool::Print((id, args))   => print::run(id, args, inputs).await?,
                 Tool::Project((id, args)) => project::run(id, args, inputs, ctx).await?,
+                Tool::Register((id, args)) => register::run(id, args, Some(inputs), ctx).await?,
+                Tool::Route((id, args))   => route::run(id, args, inputs).await?,
                 Tool::Schema(id)          => schema::run(id, inputs).await?,
                 Tool::Select((id, args))  => select::run(id, args, inputs).await?,
                 Tool::Sort((id, args))    => sort::run(id, args, inputs).await?,
                 Tool::Union((id, _))      => union::run(id, inputs).await?,
+                Tool::Window((id, args))  => window::run(id, args, inputs).await?,
                 _ => unreachable!("{} is not a sink tool", self.name())
             }
         };
@@ -112,9 +160,30 @@ impl Tool {
         Ok(outputs)
     }
 
+    /// Outputs of the tool built without executing anything, or None
+    /// if they can't be known until it runs. Tools which only plan
+    /// their work run as normal, their dataframes are never executed,
+    /// while those with side effects or executing eagerly are skipped.
+    pub async fn infer(&self, inputs: Option<Values>, ctx: &SessionContext) -> Result<Option<Values>>
+    {
+        let outputs = match self {
+            Tool::Cache(_) | Tool::Print(_) => inputs,
+            Tool::Count((_, args))    => Some(count::infer(args, ctx)?),
+            Tool::Describe(_)         => None,
+            Tool::Output(_)           => Some(Values::default()),
+            Tool::Register((_, args)) => if args.is_source() { None } else { inputs },
+            Tool::Sql((_, args)) if sql::is_query(args) => None,
+            _ => Some(self.run(inputs, ctx).await?),
+        };
+
+        Ok(outputs)
+    }
+
     pub fn name(&self) -> &str
     {
         match self {
+            Tool::Aggregate(_) => "aggregate",
+            Tool::Cache(_)     => "cache",
             Tool::Count(_)     => "count",
             Tool::Describe(_)  => "describe",
             Tool::Distinct(_)  => "distinct",
@@ -129,17 +198,50 @@ impl Tool {
             Tool::Print(_)     => "print",
             Tool::Project(_)   => "project",
             Tool::Register(_)  => "register",
+            Tool::Route(_)     => "route",
             Tool::Schema(_)    => "schema",
             Tool::Select(_)    => "select",
             Tool::Sort(_)      => "sort",
             Tool::Sql(_)       => "sql",
             Tool::Union(_)     => "union",
+            Tool::Window(_)    => "window",
+        }
+    }
+
+    pub fn spec(&self) -> &'static ToolSpec
+    {
+        match self {
+            Tool::Aggregate(_) => &aggregate::SPEC,
+            Tool::Cache(_)     => &cache::SPEC,
+            Tool::Count(_)     => &count::SPEC,
+            Tool::Describe(_)  => &describe::SPEC,
+            Tool::Distinct(_)  => &distinct::SPEC,
+            Tool::Drop(_)      => &drop::SPEC,
+            Tool::Fill(_)      => &fill::SPEC,
+            Tool::Filter(_)    => &filter::SPEC,
+            Tool::Input(_)     => &input::SPEC,
+            Tool::Intersect(_) => &intersect::SPEC,
+            Tool::Join(_)      => &join::SPEC,
+            Tool::Limit(_)     => &limit::SPEC,
+            Tool::Output(_)    => &output::SPEC,
+            Tool::Print(_)     => &print::SPEC,
+            Tool::Project(_)   => &project::SPEC,
+            Tool::Register(_)  => &register::SPEC,
+            Tool::Route(_)     => &route::SPEC,
+            Tool::Schema(_)    => &schema::SPEC,
+            Tool::Select(_)    => &select::SPEC,
+            Tool::Sort(_)      => &sort::SPEC,
+            Tool::Sql(_)       => &sql::SPEC,
+            Tool::Union(_)     => &union::SPEC,
+            Tool::Window(_)    => &window::SPEC,
         }
     }
 
     pub fn id(&self) -> ToolId
     {
         match self {
+            Tool::Aggregate((id, _)) => *id,
+            Tool::Cache(id)          => *id,
             Tool::Count((id, _))     => *id,
             Tool::Describe(id)       => *id,
             Tool::Distinct(id)       => *id,
@@ -154,11 +256,13 @@ impl Tool {
             Tool::Print((id, _))     => *id,
             Tool::Project((id, _))   => *id,
             Tool::Register((id, _))  => *id,
+            Tool::Route((id, _))     => *id,
             Tool::Schema(id)         => *id,
             Tool::Select((id, _))    => *id,
             Tool::Sort((id, _))      => *id,
             Tool::Sql((id, _))       => *id,
             Tool::Union((id, _))     => *id,
+            Tool::Window((id, _))    => *id,
         }
     }
 
@@ -167,13 +271,115 @@ impl Tool {
         match self {
             Tool::Join((_, args))      => join::flows(args),
             Tool::Intersect((_, args)) => intersect::flows(args),
+            Tool::Sql((_, args))       => sql::flows(args),
             Tool::Union((_, args))     => union::flows(args),
             _ => vec![],
         }
     }
 
+    /// Ports the tool reads from, the default port for piped input
+    /// followed by those of its flow arguments.
+    pub fn inputs(&self) -> Vec<Symbol>
+    {
+        let mut ports = self.spec().inputs.iter()
+            .map(|port| intern(port))
+            .collect::<Vec<_>>();
+        for flow in self.expand() {
+            if !ports.contains(&flow.port) {
+                ports.push(flow.port);
+            }
+        }
+
+        ports
+    }
+
+    /// Ports the tool writes to, the first receives piped output when
+    /// the tool has no default port, e.g. the true rows of a filter.
+    pub fn outputs(&self) -> Vec<Symbol>
+    {
+        if let Tool::Route((_, args)) = self {
+            return route::outputs(args)
+        }
+
+        self.spec().outputs.iter()
+            .map(|port| intern(port))
+            .collect()
+    }
+
+    /// Whether the tool needs piped input to run. Source tools read
+    /// their own data and sql queries may only read tables.
+    pub fn requires_input(&self) -> bool
+    {
+        !self.is_source()
+            && !matches!(self, Tool::Sql(_))
+            && self.spec().inputs.contains(&"*")
+    }
+
     pub fn is_source(&self) -> bool
     {
-        matches!(self, Tool::Input(_) | Tool::Register(_))
+        match self {
+            Tool::Input(_) => true,
+            Tool::Register((_, args)) => args.is_source(),
+            _ => false,
+        }
+    }
+
+    /// Resources created by running the tool.
+    pub fn produces(&self) -> Vec<Resource>
+    {
+        match self {
+            Tool::Output((_, args))   => vec![Resource::path(output::path(args))],
+            Tool::Register((_, args)) => vec![Resource::Table(register::table(args))],
+            Tool::Sql((_, args))      => sql::creates(args).iter().cloned().map(Resource::Table).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Resources which must exist before the tool runs.
+    pub fn consumes(&self) -> Vec<Resource>
+    {
+        match self {
+            Tool::Input((_, args))    => vec![Resource::path(input::path(args))],
+            Tool::Register((_, args)) => register::path(args).map(Resource::path).into_iter().collect(),
+            Tool::Sql((_, args))      => sql::tables(args).iter().cloned().map(Resource::Table).collect(),
+            _ => vec![],
+        }
     }
 }
+
+/// Argument and port descriptions for each tool type.
+pub fn spec(tool: ToolType) -> &'static ToolSpec
+{
+    match tool {
+        ToolType::Aggregate => &aggregate::SPEC,
+        ToolType::Cache     => &cache::SPEC,
+        ToolType::Count     => &count::SPEC,
+        ToolType::Describe  => &describe::SPEC,
+        ToolType::Distinct  => &distinct::SPEC,
+        ToolType::Drop      => &drop::SPEC,
+        ToolType::Fill      => &fill::SPEC,
+        ToolType::Filter    => &filter::SPEC,
+        ToolType::Input     => &input::SPEC,
+        ToolType::Intersect => &intersect::SPEC,
+        ToolType::Join      => &join::SPEC,
+        ToolType::Limit     => &limit::SPEC,
+        ToolType::Output    => &output::SPEC,
+        ToolType::Print     => &print::SPEC,
+        ToolType::Project   => &project::SPEC,
+        ToolType::Register  => &register::SPEC,
+        ToolType::Route     => &route::SPEC,
+        ToolType::Schema    => &schema::SPEC,
+        ToolType::Select    => &select::SPEC,
+        ToolType::Sort      => &sort::SPEC,
+        ToolType::Sql       => &sql::SPEC,
+        ToolType::Union     => &union::SPEC,
+        ToolType::Window    => &window::SPEC,
+    }
+}
+
+/// Check a tool taking no arguments wasn't given any.
+fn no_args(tr: &ToolRef, spec: &'static ToolSpec) -> Result<ToolId>
+{
+    ToolArgs::new(tr, spec)?;
+    Ok(tr.id)
+}
@@ -1,7 +1,20 @@
 use anyhow::{anyhow, Result};
 
 use anvil_context::syms;
-use crate::tools::{Flow, FlowRef, ToolArgs, ToolId, ToolRef, Values};
+use crate::tools::{ArgSpec, ArgType, Flow, FlowRef, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "union",
+    description: "Rows of both flows",
+    positional: &[
+        ArgSpec { name: "left", ty: ArgType::Flow, required: true, default: None, values: &[], description: "Left flow" },
+        ArgSpec { name: "right", ty: ArgType::Flow, required: true, default: None, values: &[], description: "Right flow" },
+    ],
+    keyword: &[],
+    named: None,
+    inputs: &["left", "right"],
+    outputs: &["*"],
+};
 
 pub async fn run(id: &ToolId, inputs: Values) -> Result<Values>
 {
@@ -34,9 +47,9 @@ impl TryFrom<&ToolRef> for UnionArgs {
 
     fn try_from(tr: &ToolRef) -> Result<Self>
     {
-        let args = ToolArgs::new(&tr.args)?;
-        let flow_lt = args.required_positional_flow(0, "left")?;
-        let flow_rt = args.required_positional_flow(1, "right")?;
+        let args = ToolArgs::new(tr, &SPEC)?;
+        let flow_lt = args.flow("left")?;
+        let flow_rt = args.flow("right")?;
 
         Ok(UnionArgs { flow_lt, flow_rt })
     }
@@ -0,0 +1,184 @@
+use anyhow::{anyhow, Result};
+use datafusion::logical_expr::{ExprFunctionExt, SortExpr, WindowFrame, WindowFrameBound, WindowFrameUnits};
+use datafusion::prelude::Expr;
+use datafusion::scalar::ScalarValue;
+
+use anvil_context::{resolve, Symbol};
+use crate::{eval_expression, eval_window_function};
+use crate::tools::{parse_expression, parse_expressions, parse_sort_keys, ArgSpec, ArgType, ArgValue, ToolArgs, ToolId, ToolRef, ToolSpec, Values};
+
+pub const SPEC: ToolSpec = ToolSpec {
+    name: "window",
+    description: "Compute window functions over partitions of rows",
+    positional: &[],
+    keyword: &[
+        ArgSpec { name: "partition", ty: ArgType::Expression, required: false, default: None, values: &[], description: "Comma separated partition expressions" },
+        ArgSpec { name: "order", ty: ArgType::Expression, required: false, default: None, values: &[], description: "Comma separated sort keys, e.g. '$t desc'" },
+        ArgSpec { name: "frame", ty: ArgType::String, required: false, default: None, values: &[], description: "Frame as 'units:start:end', e.g. 'rows:-2:0'" },
+    ],
+    named: Some(ArgSpec { name: "<column>", ty: ArgType::Expression, required: false, default: None, values: &[], description: "Window function for a new column" }),
+    inputs: &["*"],
+    outputs: &["*"],
+};
+
+pub async fn run(id: &ToolId, args: &WindowArgs, inputs: Values) -> Result<Values>
+{
+    let df = inputs.get_one().cloned()
+        .ok_or_else(|| anyhow!("window tool ({id}) requires input"))?;
+    let df = df.window(args.exprs.clone())?;
+
+    Ok(Values::new(df))
+}
+
+#[derive(Debug)]
+pub struct WindowArgs {
+    exprs: Vec<Expr>,
+}
+
+impl TryFrom<&ToolRef> for WindowArgs {
+    type Error = anyhow::Error;
+
+    fn try_from(tr: &ToolRef) -> Result<Self>
+    {
+        let args = ToolArgs::new(tr, &SPEC)?;
+
+        let mut partition = Vec::new();
+        if let Some(s) = args.optional_string("partition")? {
+            partition = parse_expressions(&s)?
+                .iter()
+                .map(eval_expression)
+                .collect::<Result<Vec<_>>>()?;
+        }
+
+        let mut order = Vec::new();
+        if let Some(s) = args.optional_string("order")? {
+            order = parse_sort_keys(&s)?
+                .iter()
+                .map(|k| Ok(eval_expression(&k.expr)?.sort(k.ascending, false)))
+                .collect::<Result<Vec<SortExpr>>>()?;
+        }
+
+        let frame = args.optional_string("frame")?
+            .map(|s| parse_frame(&s))
+            .transpose()?;
+
+        let mut funcs: Vec<(Symbol, Expr)> = Vec::new();
+        for (ident, value) in args.named() {
+            let ArgValue::String(s) = value else {
+                return Err(anyhow!("window tool expression must be a string {value:?}"))
+            };
+            let expr = parse_expression(s)?;
+            funcs.push((ident, eval_window_function(&expr)?));
+        }
+
+        if funcs.is_empty() {
+            return Err(anyhow!("window tool requires at least one window function"))
+        }
+
+        let mut exprs = Vec::new();
+        for (ident, func) in funcs {
+            let mut builder = func
+                .partition_by(partition.clone())
+                .order_by(order.clone());
+            if let Some(frame) = &frame {
+                builder = builder.window_frame(frame.clone());
+            }
+            exprs.push(builder.build()?.alias(resolve(ident)));
+        }
+
+        Ok(WindowArgs { exprs })
+    }
+}
+
+/// Frames are written as 'units:start:end', e.g. 'rows:-2:0' or
+/// 'rows:unbounded:0'. Negative offsets precede the current row,
+/// positive offsets follow it and 0 is the current row.
+fn parse_frame(frame: &str) -> Result<WindowFrame>
+{
+    let parts = frame.split(':').map(str::trim).collect::<Vec<_>>();
+    let [units, start, end] = parts[..] else {
+        return Err(anyhow!("window frame must be 'units:start:end': {frame}"))
+    };
+
+    let units = match units {
+        "rows"   => WindowFrameUnits::Rows,
+        "range"  => WindowFrameUnits::Range,
+        "groups" => WindowFrameUnits::Groups,
+        _ => return Err(anyhow!("window frame units must be 'rows', 'range' or 'groups': {units}"))
+    };
+
+    let start = parse_bound(start, WindowFrameBound::Preceding(ScalarValue::UInt64(None)))?;
+    let end   = parse_bound(end, WindowFrameBound::Following(ScalarValue::UInt64(None)))?;
+
+    Ok(WindowFrame::new_bounds(units, start, end))
+}
+
+fn parse_bound(bound: &str, unbounded: WindowFrameBound) -> Result<WindowFrameBound>
+{
+    if bound == "unbounded" {
+        return Ok(unbounded)
+    }
+
+    let n = bound.parse::<i64>()
+        .map_err(|_| anyhow!("window frame bound must be an integer or 'unbounded': {bound}"))?;
+    let offset = ScalarValue::UInt64(Some(n.unsigned_abs()));
+
+    let bound = match n {
+        n if n < 0 => WindowFrameBound::Preceding(offset),
+        0 => WindowFrameBound::CurrentRow,
+        _ => WindowFrameBound::Following(offset),
+    };
+
+    Ok(bound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offset(n: u64) -> ScalarValue
+    {
+        ScalarValue::UInt64(Some(n))
+    }
+
+    #[test]
+    fn frame_bounds()
+    {
+        let frame = parse_frame("rows:-2:0").unwrap();
+        assert_eq!(frame.units, WindowFrameUnits::Rows);
+        assert_eq!(frame.start_bound, WindowFrameBound::Preceding(offset(2)));
+        assert_eq!(frame.end_bound, WindowFrameBound::CurrentRow);
+
+        let frame = parse_frame("groups:0:3").unwrap();
+        assert_eq!(frame.units, WindowFrameUnits::Groups);
+        assert_eq!(frame.start_bound, WindowFrameBound::CurrentRow);
+        assert_eq!(frame.end_bound, WindowFrameBound::Following(offset(3)));
+    }
+
+    #[test]
+    fn frame_unbounded()
+    {
+        let frame = parse_frame("range:unbounded:unbounded").unwrap();
+        assert_eq!(frame.units, WindowFrameUnits::Range);
+        assert_eq!(frame.start_bound, WindowFrameBound::Preceding(ScalarValue::UInt64(None)));
+        assert_eq!(frame.end_bound, WindowFrameBound::Following(ScalarValue::UInt64(None)));
+    }
+
+    #[test]
+    fn frame_spaces()
+    {
+        let frame = parse_frame(" rows : unbounded : 1 ").unwrap();
+        assert_eq!(frame.start_bound, WindowFrameBound::Preceding(ScalarValue::UInt64(None)));
+        assert_eq!(frame.end_bound, WindowFrameBound::Following(offset(1)));
+    }
+
+    #[test]
+    fn frame_errors()
+    {
+        assert!(parse_frame("rows:0").is_err());
+        assert!(parse_frame("rows:0:1:2").is_err());
+        assert!(parse_frame("cols:0:1").is_err());
+        assert!(parse_frame("rows:first:0").is_err());
+        assert!(parse_frame("rows:0:1.5").is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::Direction::Incoming;
+
+use anvil_context::{resolve, syms, Symbol};
+use anvil_parse::{Diagnostic, Diagnostics};
+use crate::tools::did_you_mean;
+use crate::{EdgeKind, ExecNode, ExecutionPlan};
+
+/// Check the nodes and edges added to the plan since it held `nodes`
+/// nodes and `edges` edges: every port an edge names must be an output
+/// of its source or an input of its target, source tools must not be
+/// given input and other tools must be. All problems are reported
+/// together.
+pub fn validate(plan: &ExecutionPlan, nodes: usize, edges: usize) -> Result<()>
+{
+    let mut problems = vec![];
+
+    for ix in (edges..plan.edge_count()).map(EdgeIndex::new) {
+        let edge = &plan[ix];
+        let (src, tgt) = plan.edge_endpoints(ix).unwrap();
+        if edge.kind == EdgeKind::Data {
+            check_edge(plan, src, tgt, edge.port, &mut problems);
+        }
+    }
+
+    for ix in (nodes..plan.node_count()).map(NodeIndex::new) {
+        check_inputs(plan, ix, &mut problems);
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Diagnostics(problems).into())
+    }
+}
+
+/// Problems with an edge are reported at its target, where the port
+/// is named.
+fn check_edge(plan: &ExecutionPlan, src: NodeIndex, tgt: NodeIndex, port: Symbol, problems: &mut Vec<Diagnostic>)
+{
+    let span = plan[tgt].span();
+    let outputs = match &plan[src] {
+        ExecNode::Tool(tool, ..) => tool.outputs(),
+        ExecNode::Variable(..)   => vec![syms().default],
+    };
+    if outputs.is_empty() {
+        let message = format!("{} produces no output for {}", plan[src], plan[tgt]);
+        problems.push(Diagnostic { message, span });
+        problems.extend(plan[tgt].uses());
+        return
+    }
+
+    if port == syms().default || outputs.contains(&port) {
+        return
+    }
+    if let ExecNode::Tool(target, ..) = &plan[tgt]
+        && target.inputs().contains(&port)
+    {
+        return
+    }
+
+    let names = outputs.iter().map(|port| resolve(*port)).collect::<Vec<_>>();
+    let message = format!(
+        "{} has no output '{}', its outputs are {}{}",
+        plan[src], resolve(port), names.join(", "), did_you_mean(resolve(port), names.iter().copied())
+    );
+    problems.push(Diagnostic { message, span });
+    problems.extend(plan[tgt].uses());
+}
+
+fn check_inputs(plan: &ExecutionPlan, ix: NodeIndex, problems: &mut Vec<Diagnostic>)
+{
+    let ExecNode::Tool(tool, span, _) = &plan[ix] else { return };
+
+    // Piped input arrives on the default port or a branch of the
+    // source, anything but the ports of the tool's flow arguments.
+    let inputs = tool.inputs();
+    let piped = plan.edges_directed(ix, Incoming)
+        .filter(|e| e.weight().kind == EdgeKind::Data)
+        .filter(|e| e.weight().port == syms().default || !inputs.contains(&e.weight().port))
+        .count();
+
+    let message = if piped > 0 && tool.is_source() {
+        format!("{} reads its own data and takes no input", plan[ix])
+    } else if piped > 0 && !inputs.contains(&syms().default) {
+        let names = inputs.iter().map(|port| resolve(*port)).collect::<Vec<_>>();
+        format!("{} takes no piped input, its inputs are {}", plan[ix], names.join(", "))
+    } else if piped == 0 && tool.requires_input() {
+        format!("{} requires input", plan[ix])
+    } else {
+        return
+    };
+
+    problems.push(Diagnostic { message, span: *span });
+    problems.extend(plan[ix].uses());
+}